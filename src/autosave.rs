@@ -0,0 +1,84 @@
+use std::time::{Duration, Instant};
+
+// Decides when unsaved changes are written without the user asking for it:
+// shortly after the last change once editing is finished, and in any case
+// every `interval` while there are unsaved changes.
+pub struct Autosave {
+    pub enabled: bool,
+    pub debounce: Duration,
+    pub interval: Duration,
+    last_attempt: Instant,
+    // whether there were unsaved changes at the last track()
+    unsaved: bool,
+}
+
+impl Default for Autosave {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            debounce: Duration::from_secs(2),
+            interval: Duration::from_secs(30),
+            last_attempt: Instant::now(),
+            unsaved: false,
+        }
+    }
+}
+
+impl Autosave {
+    // Starts the interval at the first change after a save, otherwise the
+    // first keystroke after a long idle time would save in the middle of an
+    // edit. `changed_at` is the time of the last unsaved change.
+    pub fn track(&mut self, changed_at: Option<Instant>) {
+        match (changed_at, self.unsaved) {
+            (Some(changed_at), false) => {
+                self.unsaved = true;
+                self.last_attempt = changed_at;
+            }
+            (None, true) => self.unsaved = false,
+            _ => {}
+        }
+    }
+
+    // `changed_at` is the time of the last unsaved change, None if there is none
+    pub fn is_due(&self, changed_at: Option<Instant>, editing: bool) -> bool {
+        let Some(changed_at) = changed_at else {
+            return false;
+        };
+        if !self.enabled {
+            return false;
+        }
+        // Don't retry a failed save on every frame
+        if self.last_attempt.elapsed() < self.debounce {
+            return false;
+        }
+        let settled = !editing && changed_at.elapsed() >= self.debounce;
+        settled || self.last_attempt.elapsed() >= self.interval
+    }
+
+    pub fn attempted(&mut self) {
+        self.last_attempt = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_change_after_idle_time_waits_for_the_interval() {
+        let mut autosave = Autosave::default();
+        let idle = Duration::from_secs(60);
+        let Some(long_ago) = Instant::now().checked_sub(idle) else {
+            return;
+        };
+        autosave.last_attempt = long_ago;
+        let changed_at = Some(Instant::now());
+        autosave.track(changed_at);
+        assert!(!autosave.is_due(changed_at, true));
+        // once the change is saved the next one starts the interval again
+        autosave.track(None);
+        autosave.last_attempt = long_ago;
+        autosave.track(changed_at);
+        assert!(!autosave.is_due(changed_at, true));
+    }
+}
@@ -0,0 +1,63 @@
+// Where the frames drawn by Ui end up: the terminal, or for tests a grid in
+// memory that is updated from the same patches the terminal gets.
+
+use std::io::{self, Stdout, Write};
+
+use crate::screen_buf::{apply_patches, Buffer, Patch};
+
+pub trait Backend {
+    // Draws every cell of `buffer`, e.g. at startup and after a resize
+    fn repaint(&mut self, buffer: &Buffer) -> io::Result<()>;
+    // Draws the cells that changed since the last frame
+    fn patch(&mut self, patches: &[Patch]) -> io::Result<()>;
+}
+
+pub struct Terminal<W: Write> {
+    out: W,
+}
+
+impl Terminal<Stdout> {
+    pub fn stdout() -> Self {
+        Self { out: io::stdout() }
+    }
+}
+
+impl<W: Write> Backend for Terminal<W> {
+    fn repaint(&mut self, buffer: &Buffer) -> io::Result<()> {
+        buffer.flush(&mut self.out)
+    }
+
+    fn patch(&mut self, patches: &[Patch]) -> io::Result<()> {
+        apply_patches(&mut self.out, patches)?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+pub struct Headless {
+    screen: Buffer,
+}
+
+#[cfg(test)]
+impl Headless {
+    // The screen as text with a style letter under every cell, see Buffer::snapshot()
+    pub fn snapshot(&self) -> String {
+        self.screen.snapshot()
+    }
+}
+
+#[cfg(test)]
+impl Backend for Headless {
+    fn repaint(&mut self, buffer: &Buffer) -> io::Result<()> {
+        self.screen = buffer.clone();
+        Ok(())
+    }
+
+    fn patch(&mut self, patches: &[Patch]) -> io::Result<()> {
+        for patch in patches {
+            self.screen.apply(patch);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,589 @@
+// Non-interactive subcommands, e.g. `todo-rs todo.txt add "Buy milk"`
+//
+// Items are selected by the number `list` prints in front of them, by their
+// stable id written as `id:<id>`, or by a case-insensitive part of the title
+// that matches exactly one item. todo.txt and Markdown lists don't store ids,
+// their items are numbered anew on every load, so ids are neither shown nor
+// accepted for them.
+
+use std::fs;
+use std::io::{self, Read, Write};
+
+use anyhow::Result;
+
+use crate::history::Change;
+use crate::item::{self, Item, ItemId};
+use crate::query::Query;
+use crate::storage::json;
+use crate::storage::Storage;
+use crate::views;
+use crate::{display_item, set_title, App, Status};
+
+pub const EXIT_OK: i32 = 0;
+// loading or saving failed
+pub const EXIT_ERROR: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_NO_MATCH: i32 = 3;
+pub const EXIT_AMBIGUOUS: i32 = 4;
+
+pub const COMMANDS: &[&str] = &[
+    "add", "list", "done", "undo", "rm", "edit", "mv", "export", "import",
+];
+
+pub fn usage() {
+    eprintln!("COMMANDS:");
+    eprintln!("    add <text>...                  add a TODO item");
+    eprintln!("    list [--todo|--done] [--json]  list items with their numbers and ids");
+    eprintln!("         [--query <query>]         only the items matching <query>, e.g.");
+    eprintln!("                                   'status:todo tag:backend due<+7d prio>=B'");
+    eprintln!("         [--view <name>]           only the items matching a saved view");
+    eprintln!("    done <item>                    mark an item as done");
+    eprintln!("    undo <item>                    mark a done item as TODO again");
+    eprintln!("    rm <item>                      delete an item");
+    eprintln!("    edit <item> <text>...          change the title of an item");
+    eprintln!("    mv <item> <top|bottom|up|down|position>");
+    eprintln!("                                   move an item within its list");
+    eprintln!("    export [<file>]                write all items as JSON to <file> or stdout");
+    eprintln!("    import [--merge] <file>        replace all items with the ones from a JSON");
+    eprintln!("                                   export (`-` for stdin), --merge updates items");
+    eprintln!("                                   with the same id and adds the others");
+    eprintln!("                                   (only adds to todo.txt and Markdown lists)");
+    eprintln!("<item> is a number printed by `list`, `id:<id>` or a part of the title.");
+    eprintln!("todo.txt and Markdown lists have no ids.");
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListFilter {
+    All,
+    Todo,
+    Done,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Add(String),
+    List {
+        filter: ListFilter,
+        json: bool,
+        // query text, parsed when the command runs
+        query: Option<String>,
+        view: Option<String>,
+    },
+    Done(String),
+    Undo(String),
+    Rm(String),
+    Edit(String, String),
+    Mv(String, String),
+    Export(Option<String>),
+    Import { path: String, merge: bool },
+}
+
+impl Command {
+    pub fn parse(name: &str, args: &[String]) -> Result<Self, String> {
+        let arg = |index: usize, what: &str| {
+            args.get(index)
+                .cloned()
+                .ok_or_else(|| format!("`{}` expects {}", name, what))
+        };
+        let text = |from: usize| {
+            let text = args[from.min(args.len())..].join(" ");
+            if text.trim().is_empty() {
+                Err(format!("`{}` expects a text", name))
+            } else if text.contains(char::is_control) {
+                Err(format!("`{}` text can't contain line breaks or control characters", name))
+            } else {
+                Ok(text)
+            }
+        };
+        let command = match name {
+            "add" => Command::Add(text(0)?),
+            "list" => {
+                let mut filter = ListFilter::All;
+                let mut json = false;
+                let mut query = None;
+                let mut view = None;
+                let mut args = args.iter();
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "--all" => filter = ListFilter::All,
+                        "--todo" => filter = ListFilter::Todo,
+                        "--done" => filter = ListFilter::Done,
+                        "--json" => json = true,
+                        "--query" => {
+                            query = Some(args.next().cloned().ok_or("`--query` expects a query")?)
+                        }
+                        "--view" => {
+                            view = Some(args.next().cloned().ok_or("`--view` expects a name")?)
+                        }
+                        other => return Err(format!("unknown list option `{}`", other)),
+                    }
+                }
+                Command::List {
+                    filter,
+                    json,
+                    query,
+                    view,
+                }
+            }
+            "done" => Command::Done(arg(0, "an item")?),
+            "undo" => Command::Undo(arg(0, "an item")?),
+            "rm" => Command::Rm(arg(0, "an item")?),
+            "edit" => Command::Edit(arg(0, "an item")?, text(1)?),
+            "mv" => Command::Mv(arg(0, "an item")?, arg(1, "a position")?),
+            "export" => Command::Export(args.first().cloned()),
+            "import" => match args {
+                [flag, path] if flag == "--merge" => Command::Import {
+                    path: path.clone(),
+                    merge: true,
+                },
+                [path] => Command::Import {
+                    path: path.clone(),
+                    merge: false,
+                },
+                _ => return Err("`import` expects [--merge] <file>".to_string()),
+            },
+            _ => return Err(format!("unknown command `{}`", name)),
+        };
+        Ok(command)
+    }
+}
+
+// Runs `command` against the loaded `app`, saves if it changed anything and
+// returns the exit code
+pub fn run(app: &mut App, storage: &mut dyn Storage, command: Command) -> i32 {
+    let ids = storage.keeps_ids();
+    let result = match command {
+        Command::List {
+            filter,
+            json,
+            query,
+            view,
+        } => {
+            let queries = match list_queries(app, query.as_deref(), view.as_deref()) {
+                Ok(queries) => queries,
+                Err(code) => return code,
+            };
+            return list(app, ids, filter, &queries, json);
+        }
+        Command::Export(path) => return export(app, ids, path.as_deref()),
+        Command::Import { path, merge } => import(app, ids, &path, merge),
+        Command::Add(text) => {
+            app.active_status = Status::Todo;
+            app.active_list_mut().cursor = app.active_items().len();
+            let mut item = Item::new("");
+            set_title(&mut item, &text, app.metadata_in_titles);
+            app.insert_item(item);
+            let index = app.active_cursor();
+            println!("{}", describe(app, ids, Status::Todo, index));
+            Ok(())
+        }
+        Command::Done(selector) => transfer(app, ids, &selector, Status::Todo),
+        Command::Undo(selector) => transfer(app, ids, &selector, Status::Done),
+        Command::Rm(selector) => select(app, ids, &selector, None).map(|(status, index)| {
+            println!("Removed {}", describe(app, ids, status, index));
+            app.list_delete();
+        }),
+        Command::Edit(selector, text) => select(app, ids, &selector, None).map(|(status, index)| {
+            let metadata_in_titles = app.metadata_in_titles;
+            app.update_item(status, index, |item| set_title(item, &text, metadata_in_titles));
+            println!("{}", describe(app, ids, status, index));
+        }),
+        Command::Mv(selector, to) => match select(app, ids, &selector, None) {
+            Ok((status, index)) => move_item(app, ids, status, index, &to),
+            Err(code) => Err(code),
+        },
+    };
+    if let Err(code) = result {
+        return code;
+    }
+
+    if app.changed_at.is_some() {
+        if let Err(err) = app.save_state(storage) {
+            eprintln!("ERROR: saving failed: {:#}", err);
+            return EXIT_ERROR;
+        }
+    }
+    EXIT_OK
+}
+
+// The queries an item has to match to be listed, from `--query` and `--view`
+fn list_queries(app: &App, query: Option<&str>, view: Option<&str>) -> Result<Vec<Query>, i32> {
+    let mut queries = Vec::new();
+    if let Some(text) = query {
+        match Query::parse(text) {
+            Ok(query) => queries.push(query),
+            Err(message) => {
+                eprintln!("ERROR: invalid query: {}", message);
+                return Err(EXIT_USAGE);
+            }
+        }
+    }
+    if let Some(name) = view {
+        match views::find(&app.views, name) {
+            Some(view) => queries.push(view.query.clone()),
+            None => {
+                eprintln!("ERROR: no view named `{}`", name);
+                return Err(EXIT_USAGE);
+            }
+        }
+    }
+    Ok(queries)
+}
+
+fn list(app: &App, ids: bool, filter: ListFilter, queries: &[Query], json: bool) -> i32 {
+    let statuses: Vec<Status> = [Status::Todo, Status::Done]
+        .into_iter()
+        .filter(|status| match filter {
+            ListFilter::All => true,
+            ListFilter::Todo => *status == Status::Todo,
+            ListFilter::Done => *status == Status::Done,
+        })
+        .collect();
+
+    let today = item::today();
+    let matching = |status: Status| -> Vec<usize> {
+        let items = &app.lists[status as usize].items;
+        (0..items.len())
+            .filter(|&index| {
+                let item = &items[index];
+                queries.iter().all(|query| query.matches(status, item, today))
+            })
+            .collect()
+    };
+
+    if json {
+        let selected: Vec<(Status, Vec<Item>)> = statuses
+            .iter()
+            .map(|&status| {
+                let items = &app.lists[status as usize].items;
+                let selected = matching(status).into_iter().map(|index| &items[index]);
+                (status, json_items(selected, ids))
+            })
+            .collect();
+        let lists: Vec<(Status, &[Item])> = selected
+            .iter()
+            .map(|(status, items)| (*status, items.as_slice()))
+            .collect();
+        return print_json(&json::Document::new(&lists));
+    }
+
+    // Numbers stay those of the whole list so they can be passed to other commands
+    let mut stdout = io::stdout().lock();
+    for status in statuses {
+        for index in matching(status) {
+            // A closed pipe (e.g. `| head`) is not an error
+            if writeln!(stdout, "{}", describe(app, ids, status, index)).is_err() {
+                return EXIT_OK;
+            }
+        }
+    }
+    EXIT_OK
+}
+
+fn export(app: &App, ids: bool, path: Option<&str>) -> i32 {
+    let lists = app.item_lists().map(|(status, items)| (status, json_items(items, ids)));
+    let lists = lists.each_ref().map(|(status, items)| (*status, items.as_slice()));
+    let document = json::Document::new(&lists);
+    match path {
+        None | Some("-") => print_json(&document),
+        Some(path) => {
+            let written = serde_json::to_string_pretty(&document)
+                .map_err(anyhow::Error::from)
+                .and_then(|text| Ok(fs::write(path, text + "\n")?));
+            match written {
+                Ok(()) => EXIT_OK,
+                Err(err) => {
+                    eprintln!("ERROR: could not write {}: {:#}", path, err);
+                    EXIT_ERROR
+                }
+            }
+        }
+    }
+}
+
+// Items for JSON output, with the id 0 of new items when the list doesn't
+// keep ids, so an import doesn't mistake them for other items
+fn json_items<'a>(items: impl IntoIterator<Item = &'a Item>, ids: bool) -> Vec<Item> {
+    items
+        .into_iter()
+        .map(|item| Item {
+            id: if ids { item.id } else { 0 },
+            ..item.clone()
+        })
+        .collect()
+}
+
+fn print_json(document: &json::Document) -> i32 {
+    let mut stdout = io::stdout().lock();
+    let written = serde_json::to_writer_pretty(&mut stdout, document)
+        .map_err(io::Error::from)
+        .and_then(|()| writeln!(stdout));
+    match written {
+        Ok(()) => EXIT_OK,
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => EXIT_OK,
+        Err(err) => {
+            eprintln!("ERROR: {}", err);
+            EXIT_ERROR
+        }
+    }
+}
+
+fn import(app: &mut App, ids: bool, path: &str, merge: bool) -> Result<(), i32> {
+    let text = if path == "-" {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text).map(|_| text)
+    } else {
+        fs::read_to_string(path)
+    };
+    let document = text
+        .map_err(anyhow::Error::from)
+        .and_then(|text| json::Document::parse(&text));
+    let document = match document {
+        Ok(document) => document,
+        Err(err) => {
+            eprintln!("ERROR: could not import {}: {:#}", path, err);
+            return Err(EXIT_ERROR);
+        }
+    };
+
+    let count = document.items.len();
+    let before = app.lists.each_ref().map(|list| list.items.clone());
+    if !merge {
+        for list in app.lists.iter_mut() {
+            list.items.clear();
+            list.cursor = 0;
+        }
+    }
+    for json::Entry { status, mut item } in document.items {
+        // Items without an id are new, anything else replaces the item with that
+        // id. Without stored ids the list's ids are just line numbers, which the
+        // imported ones have nothing to do with.
+        if !ids {
+            item.id = 0;
+        }
+        if item.id != 0 {
+            for list in app.lists.iter_mut() {
+                list.items.retain(|existing| existing.id != item.id);
+                list.cursor = list.cursor.min(list.items.len().saturating_sub(1));
+            }
+        }
+        let title = Item::flatten_title(&item.title);
+        app.lists[status as usize].items.push(Item { title, ..item });
+    }
+    app.assign_ids();
+    let after = app.lists.each_ref().map(|list| list.items.clone());
+    app.record(Change::Replace { before, after });
+    println!("Imported {} item(s)", count);
+    Ok(())
+}
+
+// "  3 [ ] (A) Buy milk #home (id:12)", without the id if the list has none
+fn describe(app: &App, ids: bool, status: Status, index: usize) -> String {
+    let item = &app.lists[status as usize].items[index];
+    let mark = match status {
+        Status::Todo => ' ',
+        Status::Done => 'x',
+    };
+    let mut text = format!("{:>3} [{}] {}", number(app, status, index), mark, display_item(item));
+    if ids {
+        text.push_str(&format!(" (id:{})", item.id));
+    }
+    text
+}
+
+// Items are numbered from 1, TODO items first, like `list` prints them
+fn number(app: &App, status: Status, index: usize) -> usize {
+    match status {
+        Status::Todo => index + 1,
+        Status::Done => app.lists[Status::Todo as usize].items.len() + index + 1,
+    }
+}
+
+// Finds the item `selector` refers to, optionally only in the list of `status`,
+// and makes it the active item of `app`. Reports why if there is none.
+fn select(
+    app: &mut App,
+    ids: bool,
+    selector: &str,
+    status: Option<Status>,
+) -> Result<(Status, usize), i32> {
+    if selector.starts_with("id:") && !ids {
+        eprintln!(
+            "ERROR: `{}`: todo.txt and Markdown lists have no ids, use the number or the title",
+            selector
+        );
+        return Err(EXIT_USAGE);
+    }
+    let statuses: Vec<Status> = match status {
+        Some(status) => vec![status],
+        None => vec![Status::Todo, Status::Done],
+    };
+    let mut matches = Vec::new();
+    for status in statuses {
+        for index in 0..app.lists[status as usize].items.len() {
+            let item = &app.lists[status as usize].items[index];
+            let found = if let Some(id) = selector.strip_prefix("id:") {
+                id.parse::<ItemId>().is_ok_and(|id| id == item.id)
+            } else if let Ok(number) = selector.parse::<usize>() {
+                number == self::number(app, status, index)
+            } else {
+                item.title
+                    .to_lowercase()
+                    .contains(&selector.to_lowercase())
+            };
+            if found {
+                matches.push((status, index));
+            }
+        }
+    }
+
+    match matches.as_slice() {
+        [] => {
+            eprintln!("ERROR: no matching item for `{}`", selector);
+            Err(EXIT_NO_MATCH)
+        }
+        [(status, index)] => {
+            app.active_status = *status;
+            app.lists[*status as usize].cursor = *index;
+            Ok((*status, *index))
+        }
+        _ => {
+            eprintln!("ERROR: `{}` matches {} items:", selector, matches.len());
+            for (status, index) in matches {
+                eprintln!("{}", describe(app, ids, status, index));
+            }
+            Err(EXIT_AMBIGUOUS)
+        }
+    }
+}
+
+fn transfer(app: &mut App, ids: bool, selector: &str, from: Status) -> Result<(), i32> {
+    let (status, index) = select(app, ids, selector, Some(from))?;
+    let id = app.lists[status as usize].items[index].id;
+    app.list_transfer();
+    let to = from ^ 1;
+    let index = app.lists[to as usize]
+        .items
+        .iter()
+        .position(|item| item.id == id)
+        .unwrap_or_default();
+    println!("{}", describe(app, ids, to, index));
+    Ok(())
+}
+
+fn move_item(app: &mut App, ids: bool, status: Status, index: usize, to: &str) -> Result<(), i32> {
+    let len = app.lists[status as usize].items.len();
+    let target = match to {
+        "top" => 0,
+        "bottom" => len - 1,
+        "up" => index.saturating_sub(1),
+        "down" => (index + 1).min(len - 1),
+        _ => match to.parse::<usize>() {
+            Ok(position) if (1..=len).contains(&position) => position - 1,
+            _ => {
+                eprintln!(
+                    "ERROR: invalid position `{}`, expected top, bottom, up, down or 1-{}",
+                    to, len
+                );
+                return Err(EXIT_USAGE);
+            }
+        },
+    };
+    while app.active_cursor() > target {
+        app.drag_up();
+    }
+    while app.active_cursor() < target {
+        app.drag_down();
+    }
+    println!("{}", describe(app, ids, status, target));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{self, scratch_dir, Format, Recovery};
+
+    const EXPORT: &str = r#"{"version": 1, "items": [
+        {"status": "todo", "id": 1, "title": "Native one"},
+        {"status": "todo", "id": 2, "title": "Native two"}
+    ]}"#;
+
+    // Runs `command` on the list `text` in the format `format` and returns the
+    // exit code and the saved list. `import.json` next to the list holds `json`.
+    fn run_on(
+        name: &str,
+        format: Format,
+        text: &str,
+        json: &str,
+        command: Command,
+    ) -> (i32, String) {
+        let dir = scratch_dir(name);
+        let path = dir.join("list");
+        fs::write(&path, text).unwrap();
+        fs::write(dir.join("import.json"), json).unwrap();
+        let mut storage = storage::open(path.to_str().unwrap(), Some(format), 0);
+        let mut app = App::new();
+        app.load_state(storage.as_mut(), |_| Recovery::Abort).unwrap();
+        let code = run(&mut app, storage.as_mut(), command);
+        (code, fs::read_to_string(&path).unwrap())
+    }
+
+    fn import(name: &str, merge: bool) -> Command {
+        let path = scratch_dir(name).join("import.json");
+        Command::Import {
+            path: path.to_str().unwrap().to_string(),
+            merge,
+        }
+    }
+
+    #[test]
+    fn merge_adds_to_lists_without_ids() {
+        let name = "cli-merge-todotxt";
+        let text = "Buy milk\nCall mom\nPay rent\n";
+        let (code, saved) = run_on(name, Format::TodoTxt, text, EXPORT, import(name, true));
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(saved, "Buy milk\nCall mom\nPay rent\nNative one\nNative two\n");
+    }
+
+    #[test]
+    fn merge_replaces_by_id_in_lists_with_ids() {
+        let name = "cli-merge-native";
+        let text = "TODO: Buy milk\n    id: 1\nTODO: Call mom\n    id: 7\n";
+        let (code, saved) = run_on(name, Format::Native, text, EXPORT, import(name, true));
+        assert_eq!(code, EXIT_OK);
+        let titles: Vec<_> = saved.lines().filter(|line| line.starts_with("TODO")).collect();
+        assert_eq!(titles, ["TODO: Call mom", "TODO: Native one", "TODO: Native two"]);
+    }
+
+    #[test]
+    fn edited_todotxt_titles_take_their_tags_along() {
+        let text = "(A) 2024-01-01 Meeting +work @office due:2024-03-05\n";
+        let edit = Command::Edit("1".to_string(), "Meeting moved".to_string());
+        let (code, saved) = run_on("cli-edit-todotxt", Format::TodoTxt, text, "", edit);
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(saved, "(A) 2024-01-01 Meeting moved\n");
+    }
+
+    #[test]
+    fn duplicate_ids_are_rejected() {
+        let name = "cli-duplicate-ids";
+        let json = EXPORT.replace("\"id\": 2", "\"id\": 1");
+        let text = "TODO: Buy milk\n    id: 1\n";
+        let (code, saved) = run_on(name, Format::Native, text, &json, import(name, true));
+        assert_eq!(code, EXIT_ERROR);
+        assert_eq!(saved, text);
+    }
+
+    #[test]
+    fn other_schema_versions_are_rejected() {
+        for version in ["0", "2"] {
+            let name = format!("cli-schema-version-{}", version);
+            let json = EXPORT.replace("\"version\": 1", &format!("\"version\": {}", version));
+            let text = "Buy milk\n";
+            let (code, saved) = run_on(&name, Format::TodoTxt, text, &json, import(&name, false));
+            assert_eq!(code, EXIT_ERROR);
+            assert_eq!(saved, text);
+        }
+    }
+}
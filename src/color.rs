@@ -0,0 +1,146 @@
+// What colours the terminal can show, from --color and the environment:
+//
+//   never, or NO_COLOR set        no colour, see Buffer for how styles are kept
+//   TERM=dumb                     no colour
+//   COLORTERM=truecolor or 24bit  #rrggbb as is
+//   TERM=*-256color               the 256-colour palette
+//   anything else                 the 16 named colours
+//
+// `always` ignores NO_COLOR and TERM=dumb. Colours the terminal can't show are
+// replaced by the closest one it can.
+
+use std::env;
+
+use crossterm::style::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColorMode {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(ColorMode::Auto),
+            "always" => Some(ColorMode::Always),
+            "never" => Some(ColorMode::Never),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Palette {
+    Mono,
+    Ansi16,
+    Ansi256,
+    #[default]
+    TrueColor,
+}
+
+// The 16 named colours in the order of their ANSI numbers, with xterm's RGB values
+const ANSI_16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+// Channel values of the 6x6x6 colour cube, palette entries 16-231
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+pub fn detect(mode: ColorMode) -> Palette {
+    let var = |name: &str| env::var(name).unwrap_or_default();
+    let term = var("TERM");
+    match mode {
+        ColorMode::Never => return Palette::Mono,
+        ColorMode::Auto if !var("NO_COLOR").is_empty() || term == "dumb" => return Palette::Mono,
+        ColorMode::Auto | ColorMode::Always => {}
+    }
+    let colorterm = var("COLORTERM");
+    if colorterm == "truecolor" || colorterm == "24bit" || term.ends_with("-direct") {
+        Palette::TrueColor
+    } else if term.contains("256color") {
+        Palette::Ansi256
+    } else {
+        Palette::Ansi16
+    }
+}
+
+impl Palette {
+    // `color` as the terminal can show it, Reset without colours
+    pub fn map(self, color: Color) -> Color {
+        match (self, color) {
+            (Palette::Mono, _) => Color::Reset,
+            (Palette::TrueColor, _) | (_, Color::Reset) => color,
+            (Palette::Ansi256, Color::Rgb { r, g, b }) => Color::AnsiValue(nearest_256((r, g, b))),
+            (Palette::Ansi256, _) => color,
+            (Palette::Ansi16, Color::Rgb { r, g, b }) => nearest_16((r, g, b)),
+            (Palette::Ansi16, Color::AnsiValue(index)) => match ANSI_16.get(index as usize) {
+                Some((named, _)) => *named,
+                None => nearest_16(ansi_rgb(index)),
+            },
+            (Palette::Ansi16, _) => color,
+        }
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let channel = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    channel(a.0, b.0) + channel(a.1, b.1) + channel(a.2, b.2)
+}
+
+fn nearest_16(rgb: (u8, u8, u8)) -> Color {
+    ANSI_16
+        .iter()
+        .min_by_key(|(_, named)| distance(rgb, *named))
+        .map(|(color, _)| *color)
+        .unwrap_or(Color::Reset)
+}
+
+// The closest of the colour cube and the grey ramp
+fn nearest_256(rgb: (u8, u8, u8)) -> u8 {
+    let level = |value: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&index| (CUBE_LEVELS[index] as i32 - value as i32).abs())
+            .unwrap_or(0) as u8
+    };
+    let cube = 16 + 36 * level(rgb.0) + 6 * level(rgb.1) + level(rgb.2);
+    let average = (rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3;
+    let grey = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+    if distance(rgb, ansi_rgb(grey)) < distance(rgb, ansi_rgb(cube)) {
+        grey
+    } else {
+        cube
+    }
+}
+
+fn ansi_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_16[index as usize].1,
+        16..=231 => {
+            let cube = index - 16;
+            let level = |value: u8| CUBE_LEVELS[value as usize];
+            (level(cube / 36), level(cube / 6 % 6), level(cube % 6))
+        }
+        _ => {
+            let value = 8 + 10 * (index - 232);
+            (value, value, value)
+        }
+    }
+}
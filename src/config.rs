@@ -0,0 +1,285 @@
+// Settings, from the lowest to the highest priority:
+//
+//   1. built-in defaults
+//   2. the global config `$XDG_CONFIG_HOME/todo-rs/config.toml` (or --config)
+//   3. the project config `.todo-rs.toml` in the working directory or the
+//      closest directory above it
+//   4. environment variables TODO_RS_FILE, TODO_RS_FORMAT, TODO_RS_THEME,
+//      TODO_RS_KEYMAP, TODO_RS_AUTOSAVE and TODO_RS_UNDO_DEPTH
+//   5. command line options
+//
+// Both config files take the same keys, a relative `file` is relative to the
+// config file it is in, so a project config can point at the project's list:
+//
+//     file = "TODO.md"
+//     format = "markdown"
+//     theme = "dark"
+//     keymap = "vim"         # or "default"
+//     autosave = 10          # seconds, or false
+//     backups = 3
+//     undo_depth = 100       # changes that can be undone
+//     undo_file = true       # keep the undo history in <file>.history
+//
+//     [keys.view]
+//     "a" = "new-item"
+//
+//     [colors]
+//     header = { fg = "dark-blue" }
+//
+// See keymap.rs and theme.rs for the tables.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::keymap::{KeyTables, Keymap};
+use crate::storage::Format;
+use crate::theme::ColorTable;
+
+pub const PROJECT_FILE: &str = ".todo-rs.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum KeymapKind {
+    #[default]
+    Default,
+    Vim,
+}
+
+impl KeymapKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(KeymapKind::Default),
+            "vim" => Some(KeymapKind::Vim),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    // the list to open when none is given on the command line
+    pub file: Option<PathBuf>,
+    pub format: Option<Format>,
+    // a preset from theme.rs
+    pub theme: String,
+    // `[colors]` tables changing the theme, with the file they are from
+    pub colors: Vec<(PathBuf, ColorTable)>,
+    pub keymap: KeymapKind,
+    // bindings from the `[keys.*]` tables on top of the defaults
+    pub keys: Keymap,
+    pub autosave: bool,
+    pub autosave_interval: Duration,
+    pub backups: usize,
+    // number of changes that can be undone
+    pub undo_depth: usize,
+    // keep the undo history in `<file>.history` between sessions
+    pub undo_file: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            file: None,
+            format: None,
+            theme: "dark".to_string(),
+            colors: Vec::new(),
+            keymap: KeymapKind::Default,
+            keys: Keymap::default(),
+            autosave: true,
+            autosave_interval: Duration::from_secs(30),
+            backups: 1,
+            undo_depth: 100,
+            undo_file: false,
+        }
+    }
+}
+
+// One config file, everything it leaves out comes from the layers below
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Layer {
+    file: Option<PathBuf>,
+    format: Option<String>,
+    theme: Option<String>,
+    keymap: Option<String>,
+    autosave: Option<AutosaveSetting>,
+    backups: Option<usize>,
+    undo_depth: Option<usize>,
+    undo_file: Option<bool>,
+    keys: Option<KeyTables>,
+    colors: Option<ColorTable>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum AutosaveSetting {
+    Enabled(bool),
+    Seconds(u64),
+}
+
+// Directory of the global config, None if neither XDG_CONFIG_HOME nor HOME is set
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => home_dir()?.join(".config"),
+    };
+    Some(base.join("todo-rs"))
+}
+
+pub fn global_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+// The closest `.todo-rs.toml` in `dir` or above it
+pub fn find_project_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+impl Config {
+    // Defaults, the global config, the project config and the environment.
+    // The global config is `explicit` (from --config), which must exist, or
+    // else the default one if there is one. Command line options are applied
+    // by the caller.
+    pub fn load(explicit: Option<&Path>) -> Result<Self> {
+        let mut config = Self::default();
+        match explicit {
+            Some(path) => config.apply_file(path, false)?,
+            None => {
+                if let Some(path) = global_path() {
+                    config.apply_file(&path, true)?;
+                }
+            }
+        }
+        let project = env::current_dir()
+            .ok()
+            .and_then(|dir| find_project_file(&dir));
+        if let Some(path) = project {
+            config.apply_file(&path, false)?;
+        }
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    // A missing file is skipped if it is `optional`
+    fn apply_file(&mut self, path: &Path, optional: bool) -> Result<()> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if optional && err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("could not read {}", path.display()))
+            }
+        };
+        let layer: Layer =
+            toml::from_str(&text).with_context(|| format!("could not parse {}", path.display()))?;
+        let invalid = |message: String| anyhow!("{}: {}", path.display(), message);
+
+        if let Some(file) = layer.file {
+            let dir = path.parent().unwrap_or(Path::new("."));
+            self.file = Some(resolve(dir, &file));
+        }
+        if let Some(name) = layer.format {
+            self.format = Some(parse_format(&name).map_err(invalid)?);
+        }
+        if let Some(theme) = layer.theme {
+            self.theme = theme;
+        }
+        if let Some(name) = layer.keymap {
+            self.keymap = parse_keymap(&name).map_err(invalid)?;
+        }
+        match layer.autosave {
+            Some(AutosaveSetting::Enabled(enabled)) => self.autosave = enabled,
+            Some(AutosaveSetting::Seconds(0)) => {
+                return Err(invalid("autosave must be at least 1 second".to_string()))
+            }
+            Some(AutosaveSetting::Seconds(seconds)) => {
+                self.autosave = true;
+                self.autosave_interval = Duration::from_secs(seconds);
+            }
+            None => {}
+        }
+        if let Some(backups) = layer.backups {
+            self.backups = backups;
+        }
+        if let Some(undo_depth) = layer.undo_depth {
+            self.undo_depth = undo_depth;
+        }
+        if let Some(undo_file) = layer.undo_file {
+            self.undo_file = undo_file;
+        }
+        if let Some(keys) = &layer.keys {
+            self.keys.apply(keys, path)?;
+        }
+        if let Some(colors) = layer.colors {
+            self.colors.push((path.to_path_buf(), colors));
+        }
+        Ok(())
+    }
+
+    fn apply_env(&mut self) -> Result<()> {
+        let var = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+        let invalid = |name: &str, message: String| anyhow!("{}: {}", name, message);
+
+        if let Some(file) = var("TODO_RS_FILE") {
+            self.file = Some(PathBuf::from(file));
+        }
+        if let Some(name) = var("TODO_RS_FORMAT") {
+            self.format = Some(parse_format(&name).map_err(|m| invalid("TODO_RS_FORMAT", m))?);
+        }
+        if let Some(theme) = var("TODO_RS_THEME") {
+            self.theme = theme;
+        }
+        if let Some(name) = var("TODO_RS_KEYMAP") {
+            self.keymap = parse_keymap(&name).map_err(|m| invalid("TODO_RS_KEYMAP", m))?;
+        }
+        if let Some(value) = var("TODO_RS_AUTOSAVE") {
+            match value.as_str() {
+                "off" | "false" | "no" => self.autosave = false,
+                seconds => match seconds.parse() {
+                    Ok(seconds) if seconds > 0 => {
+                        self.autosave = true;
+                        self.autosave_interval = Duration::from_secs(seconds);
+                    }
+                    _ => {
+                        let message = format!("invalid autosave interval `{}`", value);
+                        return Err(invalid("TODO_RS_AUTOSAVE", message));
+                    }
+                },
+            }
+        }
+        if let Some(value) = var("TODO_RS_UNDO_DEPTH") {
+            self.undo_depth = value.parse().map_err(|_| {
+                invalid("TODO_RS_UNDO_DEPTH", format!("invalid undo depth `{}`", value))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_format(name: &str) -> Result<Format, String> {
+    Format::from_name(name).ok_or_else(|| format!("unknown format `{}`", name))
+}
+
+fn parse_keymap(name: &str) -> Result<KeymapKind, String> {
+    KeymapKind::from_name(name).ok_or_else(|| format!("unknown keymap `{}`", name))
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+// `~/` is the home directory, other relative paths are relative to `dir`
+fn resolve(dir: &Path, path: &Path) -> PathBuf {
+    if let (Ok(rest), Some(home)) = (path.strip_prefix("~"), home_dir()) {
+        return home.join(rest);
+    }
+    dir.join(path)
+}
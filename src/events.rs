@@ -0,0 +1,82 @@
+// Where the UI loop gets its events from: the terminal, or for tests a
+// script of keys like "insert 'abc' enter right tab"
+
+use std::io;
+use std::time::Duration;
+
+use crossterm::event::{self, Event};
+
+pub trait EventSource {
+    // The next event if one arrives within `timeout`
+    fn next(&mut self, timeout: Duration) -> io::Result<Option<Event>>;
+}
+
+pub struct TerminalEvents;
+
+impl EventSource for TerminalEvents {
+    fn next(&mut self, timeout: Duration) -> io::Result<Option<Event>> {
+        if event::poll(timeout)? {
+            event::read().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+pub use script::Script;
+
+#[cfg(test)]
+mod script {
+    use std::collections::VecDeque;
+
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::*;
+    use crate::keymap::Key;
+
+    // Events handed out in order, without waiting
+    #[derive(Default)]
+    pub struct Script {
+        events: VecDeque<Event>,
+    }
+
+    impl Script {
+        // Queues the keys of `script`: key names as in the config files
+        // separated by spaces, and text in single quotes typed a char at a time
+        pub fn push(&mut self, script: &str) -> Result<(), String> {
+            let mut rest = script.trim_start();
+            while !rest.is_empty() {
+                if let Some(quoted) = rest.strip_prefix('\'') {
+                    let end = quoted.find('\'').ok_or("unterminated text")?;
+                    for c in quoted[..end].chars() {
+                        let event = KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+                        self.events.push_back(Event::Key(event));
+                    }
+                    rest = &quoted[end + 1..];
+                } else {
+                    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                    let key = Key::parse(&rest[..end])?;
+                    self.events.push_back(Event::Key(key.to_event()));
+                    rest = &rest[end..];
+                }
+                rest = rest.trim_start();
+            }
+            Ok(())
+        }
+
+        pub fn push_event(&mut self, event: Event) {
+            self.events.push_back(event);
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.events.is_empty()
+        }
+    }
+
+    impl EventSource for Script {
+        fn next(&mut self, _timeout: Duration) -> io::Result<Option<Event>> {
+            Ok(self.events.pop_front())
+        }
+    }
+}
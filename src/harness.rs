@@ -0,0 +1,323 @@
+// Runs the UI loop on scripted keys, against a list file in a scratch directory
+// and a headless screen, and checks what the keys did to the lists and the file
+//
+//     let mut harness = Harness::new("TODO: Buy milk\n");
+//     harness.keys("insert enter 'Call mom' enter right");
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use crossterm::event::Event;
+
+use crate::autosave::Autosave;
+use crate::backend::Headless;
+use crate::events::{EventSource, Script};
+use crate::screen_buf::VirtualScreen;
+use crate::storage::{self, Format, Recovery, Storage, StorageFile};
+use crate::theme::Theme;
+use crate::ui::Ui;
+use crate::vim::Vim;
+use crate::{step, App, Status};
+
+const SIZE: (usize, usize) = (60, 10);
+
+// gives every harness a scratch directory of its own, tests run in parallel
+static HARNESSES: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Harness {
+    pub app: App,
+    ui: Ui<Headless>,
+    events: Script,
+    storage: Box<dyn Storage>,
+    // the list file, saved to by the app like any other
+    file: PathBuf,
+    autosave: Autosave,
+    theme: Theme,
+    // false once the app quit
+    pub running: bool,
+}
+
+impl Harness {
+    // The app with the native list `text` loaded
+    pub fn new(text: &str) -> Self {
+        let number = HARNESSES.fetch_add(1, Ordering::Relaxed);
+        let file = storage::scratch_dir(&format!("harness-{}", number)).join("todo.txt");
+        fs::write(&file, text).expect("the list should be written");
+        let storage_file = StorageFile::new(file.to_str().expect("the path should be UTF-8"));
+        let mut storage = storage::with_format(storage_file, Format::Native);
+        let mut app = App::new();
+        app.load_state(storage.as_mut(), |_| Recovery::Abort)
+            .expect("the list should load");
+        let theme = Theme::default();
+        let mut screen = VirtualScreen::new(SIZE.0, SIZE.1);
+        screen.set_blank(theme.normal.fg, theme.normal.bg);
+        let mut autosave = Autosave::default();
+        autosave.enabled = false;
+        Self {
+            app,
+            ui: Ui::new(screen, Headless::default()),
+            events: Script::default(),
+            storage,
+            file,
+            autosave,
+            theme,
+            running: true,
+        }
+    }
+
+    pub fn vim(mut self) -> Self {
+        self.app.vim = Some(Vim::default());
+        self
+    }
+
+    // Presses the keys of `script` (see events::Script), one loop pass per key
+    // like a user typing them
+    pub fn keys(&mut self, script: &str) -> &mut Self {
+        let mut keys = Script::default();
+        keys.push(script).expect("the script should parse");
+        while let Some(event) = keys.next(Duration::ZERO).unwrap() {
+            assert!(self.running, "keys left after quitting: {}", script);
+            self.send(event);
+        }
+        self
+    }
+
+    // Pastes `text` like a terminal with bracketed paste
+    pub fn paste(&mut self, text: &str) -> &mut Self {
+        self.send(Event::Paste(text.to_string()));
+        self
+    }
+
+    // Resizes the terminal to `width` x `height`
+    pub fn resize(&mut self, width: u16, height: u16) -> &mut Self {
+        self.send(Event::Resize(width, height));
+        self
+    }
+
+    fn send(&mut self, event: Event) {
+        self.events.push_event(event);
+        self.running = step(
+                &mut self.app,
+                &mut self.ui,
+                &mut self.events,
+                self.storage.as_mut(),
+                &mut self.autosave,
+                &self.theme,
+            )
+            .expect("the loop should not fail");
+    }
+
+    pub fn titles(&self, status: Status) -> Vec<&str> {
+        let items = &self.app.lists[status as usize].items;
+        items.iter().map(|item| item.title.as_str()).collect()
+    }
+
+    // The item lines of the file, without the metadata lines below them
+    pub fn saved(&self) -> Vec<String> {
+        let file = fs::read_to_string(&self.file).expect("the list should be readable");
+        let lines = file.lines().filter(|line| !line.starts_with(char::is_whitespace));
+        lines.map(str::to_string).collect()
+    }
+
+    // Writes `text` to the list file like another program would
+    pub fn write_file(&self, text: &str) {
+        fs::write(&self.file, text).expect("the list should be written");
+        // Coarse file system clocks could hide the change otherwise
+        let later = SystemTime::now() + Duration::from_secs(1);
+        let file = fs::File::options().write(true).open(&self.file).unwrap();
+        file.set_modified(later).expect("the modification time should be set");
+    }
+
+    pub fn screen(&self) -> String {
+        self.ui.backend().snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::Mode;
+
+    const LIST: &str = "TODO: Buy milk\nTODO: Write report\nDONE: Pay rent\n";
+
+    #[test]
+    fn new_item_is_typed_and_marked_done() {
+        let mut harness = Harness::new(LIST);
+        harness.keys("insert enter 'abc' enter right tab");
+        assert_eq!(harness.titles(Status::Todo), ["Buy milk", "Write report"]);
+        assert_eq!(harness.titles(Status::Done), ["Pay rent", "abc"]);
+        assert_eq!(harness.app.active_status, Status::Done);
+    }
+
+    #[test]
+    fn edit_with_readline_keys() {
+        let mut harness = Harness::new(LIST);
+        harness.keys("down enter ctrl+w 'notes' ctrl+a alt+d 'Read' enter");
+        assert_eq!(harness.titles(Status::Todo), ["Buy milk", "Read notes"]);
+        assert!(!harness.app.edit_mode);
+    }
+
+    #[test]
+    fn save_writes_the_file() {
+        let mut harness = Harness::new(LIST);
+        harness.keys("down right ctrl+s");
+        assert_eq!(harness.saved(), ["TODO: Buy milk", "DONE: Pay rent", "DONE: Write report"]);
+        assert_eq!(harness.app.message.as_deref(), Some("Saved"));
+    }
+
+    #[test]
+    fn quit_saves_and_stops() {
+        let mut harness = Harness::new(LIST);
+        harness.keys("delete q");
+        assert!(!harness.running);
+        assert_eq!(harness.saved(), ["TODO: Write report", "DONE: Pay rent"]);
+    }
+
+    #[test]
+    fn changes_on_disk_are_only_overwritten_when_confirmed() {
+        let mut harness = Harness::new(LIST);
+        harness.keys("delete");
+        harness.write_file("TODO: Written elsewhere\n");
+        harness.keys("ctrl+s");
+        assert_eq!(harness.saved(), ["TODO: Written elsewhere"]);
+        assert!(harness.app.status_error().is_some());
+        harness.keys("ctrl+s");
+        assert_eq!(harness.saved(), ["TODO: Write report", "DONE: Pay rent"]);
+        assert!(harness.app.status_error().is_none());
+
+        harness.keys("delete");
+        harness.write_file("TODO: Written elsewhere\n");
+        harness.keys("q");
+        assert!(harness.running);
+        assert_eq!(harness.saved(), ["TODO: Written elsewhere"]);
+        harness.keys("q");
+        assert!(!harness.running);
+        assert_eq!(harness.saved(), ["DONE: Pay rent"]);
+    }
+
+    #[test]
+    fn nothing_is_saved_without_asking() {
+        let mut harness = Harness::new(LIST);
+        harness.keys("delete delete");
+        assert_eq!(harness.saved(), ["TODO: Buy milk", "TODO: Write report", "DONE: Pay rent"]);
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut harness = Harness::new(LIST);
+        harness.keys("delete u");
+        assert_eq!(harness.titles(Status::Todo), ["Buy milk", "Write report"]);
+        harness.keys("ctrl+r");
+        assert_eq!(harness.titles(Status::Todo), ["Write report"]);
+    }
+
+    #[test]
+    fn search_moves_to_the_match() {
+        let mut harness = Harness::new(LIST);
+        harness.keys("/ 'report' enter");
+        assert_eq!(harness.app.active_cursor(), 1);
+        harness.keys("esc");
+        assert!(harness.app.search.is_none());
+        assert!(harness.running);
+    }
+
+    #[test]
+    fn vim_delete_and_paste() {
+        let mut harness = Harness::new(LIST).vim();
+        harness.keys("d d p");
+        assert_eq!(harness.titles(Status::Todo), ["Write report", "Buy milk"]);
+        harness.keys("u u");
+        assert_eq!(harness.titles(Status::Todo), ["Buy milk", "Write report"]);
+    }
+
+    #[test]
+    fn pasted_line_breaks_become_spaces() {
+        let mut harness = Harness::new(LIST);
+        harness.keys("enter end").paste(" and eggs\nDONE: Steal").keys("enter ctrl+s");
+        assert_eq!(harness.titles(Status::Todo), ["Buy milk and eggs DONE: Steal", "Write report"]);
+        assert_eq!(harness.saved().len(), 3);
+    }
+
+    #[test]
+    fn counted_vim_commands_are_undone_at_once() {
+        let list = "TODO: a\nTODO: b\nTODO: c\nTODO: d\n";
+        let mut harness = Harness::new(list).vim();
+        harness.keys("3 d d");
+        assert_eq!(harness.titles(Status::Todo), ["d"]);
+        harness.keys("u");
+        assert_eq!(harness.titles(Status::Todo), ["a", "b", "c", "d"]);
+        harness.keys("ctrl+r");
+        assert_eq!(harness.titles(Status::Todo), ["d"]);
+        harness.keys("u 2 x");
+        assert_eq!(harness.titles(Status::Done), ["a", "b"]);
+        harness.keys("u");
+        assert_eq!(harness.titles(Status::Done), [] as [&str; 0]);
+        harness.keys("y y 3 p u");
+        assert_eq!(harness.titles(Status::Todo), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn vim_paste_with_a_count() {
+        let mut harness = Harness::new("TODO: a\nTODO: b\nTODO: x\n").vim();
+        harness.keys("2 y y G 2 p");
+        assert_eq!(harness.titles(Status::Todo), ["a", "b", "x", "a", "b", "a", "b"]);
+        assert_eq!(harness.app.active_cursor(), 3);
+    }
+
+    #[test]
+    fn rebound_keys() {
+        let mut harness = Harness::new(LIST);
+        harness.app.keymap.bind(Mode::View, "x", "delete").unwrap();
+        harness.app.keymap.bind(Mode::View, "delete", "none").unwrap();
+        harness.keys("delete x");
+        assert_eq!(harness.titles(Status::Todo), ["Write report"]);
+    }
+
+    #[test]
+    fn key_breaking_a_sequence_counts_on_its_own() {
+        let mut harness = Harness::new(LIST);
+        harness.app.keymap.bind(Mode::View, "z z", "delete").unwrap();
+        harness.keys("z down");
+        assert_eq!(harness.app.active_cursor(), 1);
+        harness.keys("z z");
+        assert_eq!(harness.titles(Status::Todo), ["Buy milk"]);
+    }
+
+    #[test]
+    fn typed_start_of_a_sequence_is_kept() {
+        let mut harness = Harness::new(LIST);
+        harness.app.keymap.bind(Mode::Edit, "j j", "finish-edit").unwrap();
+        harness.keys("enter 'ja'");
+        assert!(harness.app.edit_mode);
+        assert_eq!(harness.titles(Status::Todo), ["Buy milkja", "Write report"]);
+        harness.keys("'jj'");
+        assert!(!harness.app.edit_mode);
+        assert_eq!(harness.titles(Status::Todo), ["Buy milkja", "Write report"]);
+    }
+
+    #[test]
+    fn resize_between_keys() {
+        let mut harness = Harness::new(LIST);
+        harness.keys("down").resize(30, 5).keys("tab").resize(12, 5);
+        insta::assert_snapshot!(harness.screen());
+        harness.resize(34, 4);
+        insta::assert_snapshot!(harness.screen());
+    }
+
+    #[test]
+    fn screen_after_editing() {
+        let mut harness = Harness::new(LIST);
+        harness.keys("down insert enter 'Call the plumber' left");
+        insta::assert_snapshot!(harness.screen());
+    }
+
+    #[test]
+    fn wide_characters_replaced_by_narrow_ones() {
+        let mut harness = Harness::new("TODO: 牛乳を買う\nTODO: Write report\n");
+        harness.keys("enter ctrl+a ctrl+k 'Buy milk 🥛' enter");
+        assert_eq!(harness.titles(Status::Todo), ["Buy milk 🥛", "Write report"]);
+        insta::assert_snapshot!(harness.screen());
+    }
+}
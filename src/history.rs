@@ -0,0 +1,312 @@
+// Undo/redo of list mutations
+//
+// Every mutation of the lists is recorded as a Change that can be applied
+// again (redo) or reverted (undo). Changes refer to items by list and index,
+// which is sound because they are always replayed in order on the exact
+// state they were recorded on.
+//
+// With `undo_file` set in the config the history is kept in a sidecar file
+// next to the list. It is only picked up again if loading the list gives
+// exactly the items it was saved with, otherwise the indices would point at
+// the wrong items. Formats that don't keep ids or creation times lose the
+// history this way more often.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::item::Item;
+use crate::storage;
+use crate::{ItemList, Status};
+
+const FILE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Change {
+    Insert {
+        status: Status,
+        index: usize,
+        item: Item,
+    },
+    Remove {
+        status: Status,
+        index: usize,
+        item: Item,
+    },
+    // drag within a list
+    Move {
+        status: Status,
+        from: usize,
+        to: usize,
+    },
+    // from the list of `from` to the end of the other list
+    Transfer {
+        from: Status,
+        index: usize,
+        before: Item,
+        after: Item,
+    },
+    Update {
+        status: Status,
+        index: usize,
+        before: Item,
+        after: Item,
+    },
+    // bulk changes like an import
+    Replace {
+        before: [Vec<Item>; 2],
+        after: [Vec<Item>; 2],
+    },
+    // changes made by one command like 3dd, in the order they were made
+    Batch(Vec<Change>),
+}
+
+impl Change {
+    // Both return the list and index of the affected item for the cursor
+    pub fn apply(&self, lists: &mut [ItemList; 2]) -> (Status, usize) {
+        match self {
+            Change::Insert {
+                status,
+                index,
+                item,
+            } => {
+                lists[*status as usize].items.insert(*index, item.clone());
+                (*status, *index)
+            }
+            Change::Remove { status, index, .. } => {
+                lists[*status as usize].items.remove(*index);
+                (*status, *index)
+            }
+            Change::Move { status, from, to } => {
+                let items = &mut lists[*status as usize].items;
+                let item = items.remove(*from);
+                items.insert(*to, item);
+                (*status, *to)
+            }
+            Change::Transfer {
+                from, index, after, ..
+            } => {
+                lists[*from as usize].items.remove(*index);
+                let to = *from ^ 1;
+                lists[to as usize].items.push(after.clone());
+                (*from, *index)
+            }
+            Change::Update {
+                status,
+                index,
+                after,
+                ..
+            } => {
+                lists[*status as usize].items[*index] = after.clone();
+                (*status, *index)
+            }
+            Change::Replace { after, .. } => {
+                for (list, items) in lists.iter_mut().zip(after.iter()) {
+                    list.items = items.clone();
+                }
+                (Status::Todo, 0)
+            }
+            // The cursor goes where the first change was made
+            Change::Batch(changes) => {
+                let mut first = None;
+                for change in changes {
+                    let at = change.apply(lists);
+                    first.get_or_insert(at);
+                }
+                first.unwrap_or((Status::Todo, 0))
+            }
+        }
+    }
+
+    pub fn revert(&self, lists: &mut [ItemList; 2]) -> (Status, usize) {
+        match self {
+            Change::Insert { status, index, .. } => {
+                lists[*status as usize].items.remove(*index);
+                (*status, *index)
+            }
+            Change::Remove {
+                status,
+                index,
+                item,
+            } => {
+                lists[*status as usize].items.insert(*index, item.clone());
+                (*status, *index)
+            }
+            Change::Move { status, from, to } => {
+                let items = &mut lists[*status as usize].items;
+                let item = items.remove(*to);
+                items.insert(*from, item);
+                (*status, *from)
+            }
+            Change::Transfer {
+                from,
+                index,
+                before,
+                ..
+            } => {
+                let to = *from ^ 1;
+                lists[to as usize].items.pop();
+                lists[*from as usize].items.insert(*index, before.clone());
+                (*from, *index)
+            }
+            Change::Update {
+                status,
+                index,
+                before,
+                ..
+            } => {
+                lists[*status as usize].items[*index] = before.clone();
+                (*status, *index)
+            }
+            Change::Replace { before, .. } => {
+                for (list, items) in lists.iter_mut().zip(before.iter()) {
+                    list.items = items.clone();
+                }
+                (Status::Todo, 0)
+            }
+            Change::Batch(changes) => {
+                let mut first = (Status::Todo, 0);
+                for change in changes.iter().rev() {
+                    first = change.revert(lists);
+                }
+                first
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct History {
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+    // maximum number of changes that can be undone, 0 disables the history
+    #[serde(skip)]
+    pub depth: usize,
+    // number of changes recorded in this session, see group()
+    #[serde(skip)]
+    recorded: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            depth: 100,
+            recorded: 0,
+        }
+    }
+}
+
+impl History {
+    pub fn record(&mut self, change: Change) {
+        if self.depth == 0 {
+            return;
+        }
+        self.undo.push(change);
+        self.recorded += 1;
+        self.redo.clear();
+        if self.undo.len() > self.depth {
+            let excess = self.undo.len() - self.depth;
+            self.undo.drain(..excess);
+        }
+    }
+
+    // Folds `change` into the last recorded one, e.g. the title typed into a
+    // freshly inserted item. Returns false if they can't be merged.
+    pub fn amend(&mut self, change: &Change) -> bool {
+        let (
+            Some(Change::Insert {
+                status,
+                index,
+                item,
+            }),
+            Change::Update {
+                status: updated_status,
+                index: updated_index,
+                before,
+                after,
+            },
+        ) = (self.undo.last_mut(), change)
+        else {
+            return false;
+        };
+        if status != updated_status || index != updated_index || item != before {
+            return false;
+        }
+        *item = after.clone();
+        self.redo.clear();
+        true
+    }
+
+    pub fn recorded(&self) -> usize {
+        self.recorded
+    }
+
+    // Folds the last `count` recorded changes into one that is undone at once
+    pub fn group(&mut self, count: usize) {
+        let count = count.min(self.undo.len());
+        if count > 1 {
+            let changes = self.undo.split_off(self.undo.len() - count);
+            self.undo.push(Change::Batch(changes));
+        }
+    }
+
+    pub fn undo(&mut self) -> Option<Change> {
+        let change = self.undo.pop()?;
+        self.redo.push(change.clone());
+        Some(change)
+    }
+
+    pub fn redo(&mut self) -> Option<Change> {
+        let change = self.redo.pop()?;
+        self.undo.push(change.clone());
+        Some(change)
+    }
+
+    // Sidecar file kept next to `list_path`
+    pub fn sidecar(list_path: &Path) -> PathBuf {
+        let mut name = list_path.as_os_str().to_owned();
+        name.push(".history");
+        PathBuf::from(name)
+    }
+
+    // Writes the history to `path` for the just saved `lists`
+    pub fn save(&self, path: &Path, lists: &[(Status, &[Item])]) -> Result<()> {
+        let file = HistoryFile {
+            version: FILE_VERSION,
+            fingerprint: fingerprint(lists)?,
+            history: self.clone(),
+        };
+        storage::write_atomic(path, &serde_json::to_string(&file)?, 0)
+    }
+
+    // The history saved to `path`, if it belongs to the loaded `lists`
+    pub fn load(path: &Path, lists: &[(Status, &[Item])], depth: usize) -> Option<Self> {
+        let text = fs::read_to_string(path).ok()?;
+        let file: HistoryFile = serde_json::from_str(&text).ok()?;
+        if file.version != FILE_VERSION || file.fingerprint != fingerprint(lists).ok()? {
+            return None;
+        }
+        let mut history = file.history;
+        history.depth = depth;
+        Some(history)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct HistoryFile {
+    version: u32,
+    fingerprint: u64,
+    history: History,
+}
+
+// FNV-1a of the serialized items, stable across builds unlike std's DefaultHasher
+fn fingerprint(lists: &[(Status, &[Item])]) -> Result<u64> {
+    let bytes = serde_json::to_vec(lists)?;
+    Ok(bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    }))
+}
@@ -0,0 +1,156 @@
+use chrono::{Local, NaiveDate, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+pub const DATETIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+pub type ItemId = u64;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Item {
+    // 0 until the item is registered with an App, see App::assign_ids()
+    pub id: ItemId,
+    pub title: String,
+    pub created: Option<NaiveDateTime>,
+    pub completed: Option<NaiveDateTime>,
+    // 'A' is the highest priority, 'Z' the lowest
+    pub priority: Option<char>,
+    pub tags: Vec<String>,
+    pub due: Option<NaiveDate>,
+    pub notes: String,
+    // heading the item is listed under in a Markdown file
+    pub section: Option<String>,
+    // `key:value` pairs from other tools that this app keeps but doesn't interpret
+    pub extensions: Vec<(String, String)>,
+}
+
+impl Item {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            created: Some(now()),
+            ..Self::default()
+        }
+    }
+
+    // Item as it was stored before metadata existed: just a title
+    pub fn from_title(title: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            ..Self::default()
+        }
+    }
+
+    // Titles are single lines in every format, a line break would start a
+    // new item in the file
+    pub fn flatten_title(title: &str) -> String {
+        title.chars().map(|c| if c.is_control() { ' ' } else { c }).collect()
+    }
+
+    pub fn complete(&mut self) {
+        self.completed = Some(now());
+    }
+
+    pub fn uncomplete(&mut self) {
+        self.completed = None;
+    }
+
+    // Metadata fields in the order they are saved, empty fields are left out
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("id", self.id.to_string())];
+        if let Some(created) = self.created {
+            fields.push(("created", created.format(DATETIME_FORMAT).to_string()));
+        }
+        if let Some(completed) = self.completed {
+            fields.push(("completed", completed.format(DATETIME_FORMAT).to_string()));
+        }
+        if let Some(priority) = self.priority {
+            fields.push(("priority", priority.to_string()));
+        }
+        if !self.tags.is_empty() {
+            fields.push(("tags", self.tags.join(", ")));
+        }
+        if let Some(due) = self.due {
+            fields.push(("due", due.format(DATE_FORMAT).to_string()));
+        }
+        for note in self.notes.lines() {
+            fields.push(("note", note.to_string()));
+        }
+        if let Some(section) = &self.section {
+            fields.push(("section", section.clone()));
+        }
+        // A todo.txt `due:` is already covered by the due field
+        for (key, value) in self.extensions.iter().filter(|(key, _)| key != "due") {
+            fields.push(("ext", format!("{}:{}", key, value)));
+        }
+        fields
+    }
+
+    pub fn set_field(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "id" => {
+                self.id = value
+                    .parse()
+                    .map_err(|_| format!("invalid id `{value}`"))?;
+            }
+            "created" => self.created = Some(parse_datetime(value)?),
+            "completed" => self.completed = Some(parse_datetime(value)?),
+            "priority" => self.priority = Some(parse_priority(value)?),
+            "tags" => {
+                self.tags = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            "due" => self.due = Some(parse_date(value)?),
+            "note" => {
+                if !self.notes.is_empty() {
+                    self.notes.push('\n');
+                }
+                self.notes.push_str(value);
+            }
+            "section" => self.section = Some(value.to_string()),
+            "ext" => {
+                let (ext_key, ext_value) = value
+                    .split_once(':')
+                    .ok_or_else(|| format!("invalid extension `{value}`, expected key:value"))?;
+                self.extensions
+                    .push((ext_key.to_string(), ext_value.to_string()));
+            }
+            _ => return Err(format!("unknown field `{key}`")),
+        }
+        Ok(())
+    }
+
+    pub fn is_overdue(&self, today: NaiveDate) -> bool {
+        self.completed.is_none() && self.due.is_some_and(|due| due < today)
+    }
+}
+
+pub fn now() -> NaiveDateTime {
+    Local::now().naive_local().with_nanosecond(0).unwrap_or_default()
+}
+
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+pub fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, DATE_FORMAT).map_err(|_| format!("invalid date `{value}`"))
+}
+
+pub fn parse_datetime(value: &str) -> Result<NaiveDateTime, String> {
+    NaiveDateTime::parse_from_str(value, DATETIME_FORMAT)
+        .map_err(|_| format!("invalid timestamp `{value}`"))
+}
+
+pub fn parse_priority(value: &str) -> Result<char, String> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_uppercase() => Ok(c),
+        _ => Err(format!("invalid priority `{value}`, expected A-Z")),
+    }
+}
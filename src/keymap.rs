@@ -0,0 +1,485 @@
+// Key bindings of the list view and edit mode, the defaults can be changed in
+// the `[keys.view]` and `[keys.edit]` tables of the config files (see
+// config.rs):
+//
+//     [keys.view]
+//     "a" = "new-item"
+//     "x" = "delete"
+//     "g g" = "top"
+//     "ctrl+n" = "new-item"
+//     "insert" = "none"
+//
+//     [keys.edit]
+//     "ctrl+j" = "finish-edit"
+//
+// A binding is a sequence of keys separated by spaces, each with optional
+// `ctrl+`, `alt+` and `shift+` modifiers. Keys are single characters or one
+// of the names in KEY_NAMES. `none` removes a default binding. In edit mode
+// characters without a binding are typed.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    // list view
+    Quit,
+    Save,
+    CursorUp,
+    CursorDown,
+    Top,
+    Bottom,
+    PageUp,
+    PageDown,
+    HalfPageUp,
+    HalfPageDown,
+    DragUp,
+    DragDown,
+    SwitchList,
+    // moves the item to the other list
+    Transfer,
+    // Transfer, but only from DONE or only from TODO
+    MarkTodo,
+    MarkDone,
+    Delete,
+    NewItem,
+    Edit,
+    Undo,
+    Redo,
+    Search,
+    Filter,
+    SearchNext,
+    SearchPrevious,
+    NextView,
+    PreviousView,
+    // clears the search or the filter, quits if there is none
+    ClearOrQuit,
+    // edit mode
+    FinishEdit,
+    Left,
+    Right,
+    Home,
+    End,
+    WordLeft,
+    WordRight,
+    Backspace,
+    DeleteForward,
+    KillWordBefore,
+    KillBigWordBefore,
+    KillWordAfter,
+    KillToStart,
+    KillToEnd,
+    Yank,
+    YankPop,
+    Transpose,
+}
+
+const VIEW_ACTIONS: &[(&str, Action)] = &[
+    ("quit", Action::Quit),
+    ("save", Action::Save),
+    ("cursor-up", Action::CursorUp),
+    ("cursor-down", Action::CursorDown),
+    ("top", Action::Top),
+    ("bottom", Action::Bottom),
+    ("page-up", Action::PageUp),
+    ("page-down", Action::PageDown),
+    ("half-page-up", Action::HalfPageUp),
+    ("half-page-down", Action::HalfPageDown),
+    ("drag-up", Action::DragUp),
+    ("drag-down", Action::DragDown),
+    ("switch-list", Action::SwitchList),
+    ("transfer", Action::Transfer),
+    ("mark-todo", Action::MarkTodo),
+    ("mark-done", Action::MarkDone),
+    ("delete", Action::Delete),
+    ("new-item", Action::NewItem),
+    ("edit", Action::Edit),
+    ("undo", Action::Undo),
+    ("redo", Action::Redo),
+    ("search", Action::Search),
+    ("filter", Action::Filter),
+    ("search-next", Action::SearchNext),
+    ("search-previous", Action::SearchPrevious),
+    ("next-view", Action::NextView),
+    ("previous-view", Action::PreviousView),
+    ("clear-or-quit", Action::ClearOrQuit),
+];
+
+const EDIT_ACTIONS: &[(&str, Action)] = &[
+    ("finish-edit", Action::FinishEdit),
+    ("left", Action::Left),
+    ("right", Action::Right),
+    ("home", Action::Home),
+    ("end", Action::End),
+    ("word-left", Action::WordLeft),
+    ("word-right", Action::WordRight),
+    ("backspace", Action::Backspace),
+    ("delete-forward", Action::DeleteForward),
+    ("kill-word-before", Action::KillWordBefore),
+    ("kill-big-word-before", Action::KillBigWordBefore),
+    ("kill-word-after", Action::KillWordAfter),
+    ("kill-to-start", Action::KillToStart),
+    ("kill-to-end", Action::KillToEnd),
+    ("yank", Action::Yank),
+    ("yank-pop", Action::YankPop),
+    ("transpose", Action::Transpose),
+];
+
+const DEFAULT_VIEW_KEYS: &[(&str, Action)] = &[
+    ("ctrl+c", Action::Quit),
+    ("q", Action::Quit),
+    ("ctrl+s", Action::Save),
+    ("ctrl+u", Action::HalfPageUp),
+    ("ctrl+d", Action::HalfPageDown),
+    ("ctrl+z", Action::Undo),
+    ("u", Action::Undo),
+    ("ctrl+r", Action::Redo),
+    ("ctrl+y", Action::Redo),
+    ("/", Action::Search),
+    ("f", Action::Filter),
+    ("n", Action::SearchNext),
+    ("N", Action::SearchPrevious),
+    ("v", Action::NextView),
+    ("V", Action::PreviousView),
+    ("esc", Action::ClearOrQuit),
+    ("enter", Action::Edit),
+    ("tab", Action::SwitchList),
+    ("up", Action::CursorUp),
+    ("down", Action::CursorDown),
+    ("ctrl+up", Action::DragUp),
+    ("ctrl+down", Action::DragDown),
+    ("pageup", Action::PageUp),
+    ("pagedown", Action::PageDown),
+    ("home", Action::Top),
+    ("end", Action::Bottom),
+    ("left", Action::MarkTodo),
+    ("right", Action::MarkDone),
+    ("delete", Action::Delete),
+    ("insert", Action::NewItem),
+];
+
+// readline/Emacs keys, see line_edit.rs
+const DEFAULT_EDIT_KEYS: &[(&str, Action)] = &[
+    ("esc", Action::FinishEdit),
+    ("enter", Action::FinishEdit),
+    ("left", Action::Left),
+    ("right", Action::Right),
+    ("ctrl+b", Action::Left),
+    ("ctrl+f", Action::Right),
+    ("home", Action::Home),
+    ("end", Action::End),
+    ("ctrl+a", Action::Home),
+    ("ctrl+e", Action::End),
+    ("ctrl+left", Action::WordLeft),
+    ("ctrl+right", Action::WordRight),
+    ("alt+b", Action::WordLeft),
+    ("alt+f", Action::WordRight),
+    ("backspace", Action::Backspace),
+    ("ctrl+h", Action::Backspace),
+    ("delete", Action::DeleteForward),
+    ("ctrl+d", Action::DeleteForward),
+    ("alt+backspace", Action::KillWordBefore),
+    ("ctrl+backspace", Action::KillWordBefore),
+    ("ctrl+w", Action::KillBigWordBefore),
+    ("alt+d", Action::KillWordAfter),
+    ("ctrl+u", Action::KillToStart),
+    ("ctrl+k", Action::KillToEnd),
+    ("ctrl+y", Action::Yank),
+    ("alt+y", Action::YankPop),
+    ("ctrl+t", Action::Transpose),
+];
+
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("esc", KeyCode::Esc),
+    ("enter", KeyCode::Enter),
+    ("tab", KeyCode::Tab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("space", KeyCode::Char(' ')),
+];
+
+// the modifiers a binding can use, others like super are ignored
+const MODIFIERS: KeyModifiers = KeyModifiers::CONTROL
+    .union(KeyModifiers::ALT)
+    .union(KeyModifiers::SHIFT);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    // Characters carry shift in their case, Shift-Tab arrives as BackTab
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers = modifiers & MODIFIERS;
+        let code = match code {
+            KeyCode::BackTab => {
+                modifiers |= KeyModifiers::SHIFT;
+                KeyCode::Tab
+            }
+            KeyCode::Char(c) => {
+                if modifiers.contains(KeyModifiers::SHIFT) {
+                    modifiers.remove(KeyModifiers::SHIFT);
+                    KeyCode::Char(c.to_uppercase().next().unwrap_or(c))
+                } else {
+                    KeyCode::Char(c)
+                }
+            }
+            code => code,
+        };
+        Self { code, modifiers }
+    }
+
+    pub fn from_event(event: &KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+
+    // The character the key types in edit mode, None for keys like ctrl+a
+    pub fn typed_char(&self) -> Option<char> {
+        match self.code {
+            KeyCode::Char(c)
+                if !self.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+
+    // e.g. "ctrl+s", "alt+backspace", "shift+tab" or "N"
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        loop {
+            let lower = rest.to_lowercase();
+            let modifier = [
+                ("ctrl+", KeyModifiers::CONTROL),
+                ("alt+", KeyModifiers::ALT),
+                ("shift+", KeyModifiers::SHIFT),
+            ]
+            .into_iter()
+            .find(|(prefix, _)| lower.starts_with(prefix) && rest.len() > prefix.len());
+            match modifier {
+                Some((prefix, modifier)) => {
+                    modifiers |= modifier;
+                    rest = &rest[prefix.len()..];
+                }
+                None => break,
+            }
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => {
+                let lower = rest.to_lowercase();
+                match KEY_NAMES.iter().find(|(name, _)| *name == lower) {
+                    Some((_, code)) => *code,
+                    None => match lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                        Some(n @ 1..=12) => KeyCode::F(n),
+                        _ => return Err(format!("unknown key `{}`", rest)),
+                    },
+                }
+            }
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+#[cfg(test)]
+impl Key {
+    // A press of the key as the terminal reports it
+    pub fn to_event(self) -> KeyEvent {
+        match (self.code, self.modifiers.contains(KeyModifiers::SHIFT)) {
+            (KeyCode::Tab, true) => KeyEvent::new(KeyCode::BackTab, self.modifiers),
+            (code, _) => KeyEvent::new(code, self.modifiers),
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, modifier) in [
+            ("ctrl+", KeyModifiers::CONTROL),
+            ("alt+", KeyModifiers::ALT),
+            ("shift+", KeyModifiers::SHIFT),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}", name)?;
+            }
+        }
+        match KEY_NAMES.iter().find(|(_, code)| *code == self.code) {
+            Some((name, _)) => write!(f, "{}", name),
+            None => match self.code {
+                KeyCode::Char(c) => write!(f, "{}", c),
+                KeyCode::F(n) => write!(f, "f{}", n),
+                code => write!(f, "{:?}", code),
+            },
+        }
+    }
+}
+
+fn parse_keys(text: &str) -> Result<Vec<Key>, String> {
+    let keys = text
+        .split_whitespace()
+        .map(Key::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    if keys.is_empty() {
+        return Err("empty key sequence".to_string());
+    }
+    Ok(keys)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    View,
+    Edit,
+}
+
+// What a key press means
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lookup {
+    Action(Action),
+    // the start of a longer sequence, wait for the next key
+    Pending,
+    Unbound,
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    view: Vec<(Vec<Key>, Action)>,
+    edit: Vec<(Vec<Key>, Action)>,
+    // keys of a sequence typed so far
+    pending: Vec<Key>,
+    // keys of a sequence the last key didn't continue, see take_broken()
+    broken: Vec<Key>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let defaults = |keys: &[(&str, Action)]| {
+            keys.iter()
+                .map(|(keys, action)| (parse_keys(keys).expect("valid default key"), *action))
+                .collect()
+        };
+        Self {
+            view: defaults(DEFAULT_VIEW_KEYS),
+            edit: defaults(DEFAULT_EDIT_KEYS),
+            pending: Vec::new(),
+            broken: Vec::new(),
+        }
+    }
+}
+
+// The `[keys.view]` and `[keys.edit]` tables of a config file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyTables {
+    #[serde(default)]
+    view: BTreeMap<String, String>,
+    #[serde(default)]
+    edit: BTreeMap<String, String>,
+}
+
+impl Keymap {
+    // Changes the bindings as the tables read from `path` say. Every invalid
+    // binding is reported, not just the first one.
+    pub fn apply(&mut self, tables: &KeyTables, path: &Path) -> Result<()> {
+        let mut errors = Vec::new();
+        for (mode, table) in [(Mode::View, &tables.view), (Mode::Edit, &tables.edit)] {
+            for (keys, action) in table {
+                if let Err(message) = self.bind(mode, keys, action) {
+                    let table = if mode == Mode::View { "view" } else { "edit" };
+                    errors.push(format!("[keys.{}] `{}`: {}", table, keys, message));
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(anyhow!(
+                "{}: invalid key bindings:\n    {}",
+                path.display(),
+                errors.join("\n    ")
+            ));
+        }
+        Ok(())
+    }
+
+    // Binds `keys` to the action named `action`, "none" removes the binding
+    pub fn bind(&mut self, mode: Mode, keys: &str, action: &str) -> Result<(), String> {
+        let keys = parse_keys(keys)?;
+        let (bindings, actions) = match mode {
+            Mode::View => (&mut self.view, VIEW_ACTIONS),
+            Mode::Edit => (&mut self.edit, EDIT_ACTIONS),
+        };
+        bindings.retain(|(bound, _)| *bound != keys);
+        if action == "none" {
+            return Ok(());
+        }
+        let Some((_, action)) = actions.iter().find(|(name, _)| *name == action) else {
+            return Err(format!("unknown action `{}`", action));
+        };
+        bindings.push((keys, *action));
+        Ok(())
+    }
+
+    // When the key doesn't continue the sequence typed so far, the sequence is
+    // given up and the key is looked up on its own
+    pub fn lookup(&mut self, mode: Mode, event: &KeyEvent) -> Lookup {
+        self.broken.clear();
+        self.lookup_key(mode, Key::from_event(event))
+    }
+
+    fn lookup_key(&mut self, mode: Mode, key: Key) -> Lookup {
+        self.pending.push(key);
+        let bindings = match mode {
+            Mode::View => &self.view,
+            Mode::Edit => &self.edit,
+        };
+        if let Some((_, action)) = bindings.iter().find(|(keys, _)| *keys == self.pending) {
+            self.pending.clear();
+            return Lookup::Action(*action);
+        }
+        if bindings
+            .iter()
+            .any(|(keys, _)| keys.len() > self.pending.len() && keys.starts_with(&self.pending))
+        {
+            return Lookup::Pending;
+        }
+        let mut pending = std::mem::take(&mut self.pending);
+        if pending.len() > 1 {
+            pending.pop();
+            self.broken = pending;
+            return self.lookup_key(mode, key);
+        }
+        Lookup::Unbound
+    }
+
+    // The keys of a sequence the last looked up key broke off, they come
+    // before it. In edit mode the characters among them are typed.
+    pub fn take_broken(&mut self) -> Vec<Key> {
+        std::mem::take(&mut self.broken)
+    }
+
+    // The keys of a sequence typed so far, for the status bar
+    pub fn pending(&self) -> String {
+        self.pending
+            .iter()
+            .map(Key::to_string)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
@@ -0,0 +1,356 @@
+// Single-line text editing with a cursor, used for item titles in edit mode
+//
+// The cursor is a byte offset into the text that always sits on a grapheme
+// cluster boundary, so moving and deleting never splits an umlaut written as
+// a letter and a combining mark, or an emoji sequence.
+//
+// The commands follow readline: killed text goes to a kill ring that is kept
+// between edits, consecutive kills are joined into one entry, Ctrl-y yanks
+// the newest entry and Alt-y right after it replaces it with older ones.
+
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::screen_buf::text_width;
+
+const KILL_RING_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Default)]
+pub struct LineEditor {
+    text: String,
+    cursor: usize,
+    // newest entry last
+    kill_ring: Vec<String>,
+    last: LastCommand,
+}
+
+// What the previous command was, kills and yanks depend on it
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum LastCommand {
+    #[default]
+    Other,
+    Kill,
+    // the yanked text is at `range`, taken from the entry `age` kills back
+    Yank { range: (usize, usize), age: usize },
+}
+
+impl LineEditor {
+    // Starts with the cursor at the end of `text`
+    pub fn new(text: &str) -> Self {
+        let mut editor = Self::default();
+        editor.reset(text);
+        editor
+    }
+
+    // Starts over with `text`, keeping the kill ring
+    pub fn reset(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = text.len();
+        self.last = LastCommand::Other;
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    // Control characters such as pasted line breaks become spaces
+    pub fn insert(&mut self, c: char) {
+        let c = if c.is_control() { ' ' } else { c };
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+        self.last = LastCommand::Other;
+    }
+
+    // Removes the grapheme before the cursor
+    pub fn backspace(&mut self) {
+        let start = self.prev_boundary();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+        self.last = LastCommand::Other;
+    }
+
+    // Removes the grapheme under the cursor
+    pub fn delete(&mut self) {
+        let end = self.next_boundary();
+        self.text.replace_range(self.cursor..end, "");
+        self.last = LastCommand::Other;
+    }
+
+    pub fn left(&mut self) {
+        self.move_to(self.prev_boundary());
+    }
+
+    pub fn right(&mut self) {
+        self.move_to(self.next_boundary());
+    }
+
+    pub fn home(&mut self) {
+        self.move_to(0);
+    }
+
+    pub fn end(&mut self) {
+        self.move_to(self.text.len());
+    }
+
+    pub fn word_left(&mut self) {
+        self.move_to(self.word_start(is_word));
+    }
+
+    pub fn word_right(&mut self) {
+        self.move_to(self.word_end());
+    }
+
+    // Alt-Backspace, up to the start of the word before the cursor
+    pub fn kill_word_before(&mut self) {
+        self.kill(self.word_start(is_word)..self.cursor, false);
+    }
+
+    // Ctrl-w, up to the whitespace before the cursor
+    pub fn kill_big_word_before(&mut self) {
+        self.kill(self.word_start(|grapheme| !is_space(grapheme))..self.cursor, false);
+    }
+
+    // Alt-d
+    pub fn kill_word_after(&mut self) {
+        self.kill(self.cursor..self.word_end(), true);
+    }
+
+    // Ctrl-u
+    pub fn kill_to_start(&mut self) {
+        self.kill(0..self.cursor, false);
+    }
+
+    // Ctrl-k
+    pub fn kill_to_end(&mut self) {
+        self.kill(self.cursor..self.text.len(), true);
+    }
+
+    // Ctrl-y, inserts the newest killed text
+    pub fn yank(&mut self) {
+        self.yank_entry(0);
+    }
+
+    // Alt-y, replaces the text just yanked with the entry killed before it
+    pub fn yank_pop(&mut self) {
+        let LastCommand::Yank { range, age } = self.last else {
+            return;
+        };
+        self.text.replace_range(range.0..range.1, "");
+        self.cursor = range.0;
+        self.yank_entry((age + 1) % self.kill_ring.len());
+    }
+
+    // Ctrl-t, swaps the graphemes around the cursor and moves past both. At
+    // the end of the text the last two are swapped.
+    pub fn transpose(&mut self) {
+        if self.cursor == self.text.len() {
+            self.cursor = self.prev_boundary();
+        }
+        let middle = self.cursor;
+        let start = self.prev_boundary();
+        let end = self.next_boundary();
+        if start < middle && middle < end {
+            let swapped = format!("{}{}", &self.text[middle..end], &self.text[start..middle]);
+            self.text.replace_range(start..end, &swapped);
+        }
+        self.cursor = end;
+        self.last = LastCommand::Other;
+    }
+
+    // Screen column of the cursor relative to the start of the text, wide
+    // graphemes take two
+    pub fn caret_column(&self) -> usize {
+        text_width(&self.text[..self.cursor])
+    }
+
+    fn move_to(&mut self, cursor: usize) {
+        self.cursor = cursor;
+        self.last = LastCommand::Other;
+    }
+
+    fn kill(&mut self, range: Range<usize>, forward: bool) {
+        if range.is_empty() {
+            return;
+        }
+        let killed: String = self.text.drain(range.clone()).collect();
+        self.cursor = range.start;
+        match self.kill_ring.last_mut() {
+            Some(newest) if self.last == LastCommand::Kill => {
+                if forward {
+                    newest.push_str(&killed);
+                } else {
+                    newest.insert_str(0, &killed);
+                }
+            }
+            _ => {
+                if self.kill_ring.len() == KILL_RING_SIZE {
+                    self.kill_ring.remove(0);
+                }
+                self.kill_ring.push(killed);
+            }
+        }
+        self.last = LastCommand::Kill;
+    }
+
+    fn yank_entry(&mut self, age: usize) {
+        let Some(entry) = self.kill_ring.iter().rev().nth(age) else {
+            return;
+        };
+        let start = self.cursor;
+        self.text.insert_str(start, entry);
+        self.cursor += entry.len();
+        self.last = LastCommand::Yank {
+            range: (start, self.cursor),
+            age,
+        };
+    }
+
+    fn prev_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(start, _)| start)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.text[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |grapheme| self.cursor + grapheme.len())
+    }
+
+    // Start of the word before the cursor, skipping what isn't part of one
+    fn word_start(&self, in_word: fn(&str) -> bool) -> usize {
+        let mut graphemes = self.text[..self.cursor].grapheme_indices(true).rev().peekable();
+        while graphemes.next_if(|(_, grapheme)| !in_word(grapheme)).is_some() {}
+        let mut start = graphemes.peek().map_or(0, |(index, _)| *index);
+        for (index, grapheme) in graphemes {
+            if !in_word(grapheme) {
+                break;
+            }
+            start = index;
+        }
+        start
+    }
+
+    // End of the word after the cursor, skipping what isn't part of one
+    fn word_end(&self) -> usize {
+        let mut graphemes = self.text[self.cursor..].grapheme_indices(true).peekable();
+        while graphemes.next_if(|(_, grapheme)| !is_word(grapheme)).is_some() {}
+        let mut end = graphemes
+            .peek()
+            .map_or(self.text.len(), |(index, _)| self.cursor + index);
+        for (index, grapheme) in graphemes {
+            if !is_word(grapheme) {
+                break;
+            }
+            end = self.cursor + index + grapheme.len();
+        }
+        end
+    }
+}
+
+fn is_word(grapheme: &str) -> bool {
+    grapheme.chars().next().is_some_and(char::is_alphanumeric)
+}
+
+fn is_space(grapheme: &str) -> bool {
+    grapheme.chars().all(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "u" with a combining diaeresis and a family emoji of three people
+    const TEXT: &str = "Mu\u{308}ller 👨\u{200d}👩\u{200d}👧!";
+
+    #[test]
+    fn backspace_and_delete_remove_whole_graphemes() {
+        let mut editor = LineEditor::new(TEXT);
+        editor.left();
+        editor.backspace();
+        assert_eq!(editor.text(), "Mu\u{308}ller !");
+        editor.home();
+        editor.right();
+        editor.delete();
+        assert_eq!(editor.text(), "Mller !");
+        editor.backspace();
+        editor.backspace();
+        assert_eq!(editor.text(), "ller !");
+        editor.end();
+        editor.delete();
+        assert_eq!(editor.text(), "ller !");
+    }
+
+    #[test]
+    fn typing_goes_to_the_cursor() {
+        let mut editor = LineEditor::new(TEXT);
+        editor.left();
+        editor.left();
+        for c in "Frau ".chars() {
+            editor.insert(c);
+        }
+        assert_eq!(editor.text(), "Mu\u{308}ller Frau 👨\u{200d}👩\u{200d}👧!");
+        assert_eq!(editor.caret_column(), 12);
+    }
+
+    #[test]
+    fn consecutive_kills_are_joined() {
+        let mut editor = LineEditor::new("buy fresh milk today");
+        editor.kill_big_word_before();
+        editor.kill_word_before();
+        assert_eq!(editor.text(), "buy fresh ");
+        editor.home();
+        editor.kill_word_after();
+        editor.kill_word_after();
+        assert_eq!(editor.text(), " ");
+        editor.end();
+        editor.yank();
+        assert_eq!(editor.text(), " buy fresh");
+        editor.yank_pop();
+        assert_eq!(editor.text(), " milk today");
+    }
+
+    #[test]
+    fn yank_pop_cycles_through_the_kill_ring() {
+        let mut editor = LineEditor::new("one two three");
+        // The motion between the kills keeps them apart
+        for _ in 0..3 {
+            editor.kill_word_before();
+            editor.end();
+        }
+        assert_eq!(editor.text(), "");
+        editor.yank();
+        editor.yank_pop();
+        assert_eq!(editor.text(), "two ");
+        editor.yank_pop();
+        editor.yank_pop();
+        assert_eq!(editor.text(), "one ");
+
+        // Only right after a yank
+        editor.insert('+');
+        editor.yank_pop();
+        assert_eq!(editor.text(), "one +");
+    }
+
+    #[test]
+    fn transpose_swaps_graphemes() {
+        let mut editor = LineEditor::new("abc");
+        editor.transpose();
+        assert_eq!(editor.text(), "acb");
+        editor.home();
+        editor.right();
+        editor.transpose();
+        assert_eq!(editor.text(), "cab");
+        assert_eq!(editor.caret_column(), 2);
+
+        let mut editor = LineEditor::new(TEXT);
+        editor.transpose();
+        assert_eq!(editor.text(), "Mu\u{308}ller !👨\u{200d}👩\u{200d}👧");
+        editor.home();
+        editor.transpose();
+        assert_eq!(editor.text(), "Mu\u{308}ller !👨\u{200d}👩\u{200d}👧");
+    }
+}
@@ -3,26 +3,62 @@
 #![allow(unused_variables)]
 
 use std::fs::File;
-use std::io::{self, stderr, stdout, BufRead, Write};
+use std::io::{self, stderr, stdout, BufRead, IsTerminal, Write};
+use std::cmp;
 use std::ops::{BitXor, BitXorAssign};
-use std::time::{Duration, SystemTime};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
 use std::{env, process, thread};
 
 use anyhow::Result;
 use crossterm::cursor::{DisableBlinking, Hide, MoveTo, SetCursorStyle, Show};
-use crossterm::event::{poll, read, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::event::{poll, read, Event, KeyCode, KeyEventKind};
 use crossterm::style::{Color, Print, SetBackgroundColor, SetForegroundColor};
 use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
 use crossterm::{execute, queue, ExecutableCommand, QueueableCommand};
 use screen_buf::{apply_patches, Buffer, VirtualScreen};
 use unicode_segmentation::UnicodeSegmentation;
 
+mod autosave;
+mod backend;
+mod cli;
+mod events;
+#[cfg(test)]
+mod harness;
+mod color;
+mod config;
+mod history;
+mod item;
+mod keymap;
+mod line_edit;
+mod query;
+mod render;
+mod search;
+mod storage;
+mod theme;
 mod ui;
 mod screen_buf;
+mod views;
+mod vim;
 
 use ui::{Layout, LayoutKind, Ui, Vec2};
 
-type Item = String;
+use item::{Item, ItemId};
+use keymap::{Action, Key, Keymap, Lookup, Mode};
+use line_edit::LineEditor;
+use serde::{Deserialize, Serialize};
+use autosave::Autosave;
+use backend::{Backend, Terminal};
+use color::ColorMode;
+use config::{Config, KeymapKind};
+use events::{EventSource, TerminalEvents};
+use history::{Change, History};
+use query::Query;
+use search::{MatchMode, Pattern, Prompt, PromptKind};
+use storage::{Format, ParseError, ParseErrors, Recovery, Storage};
+use theme::Theme;
+use views::View;
+use vim::Vim;
 
 struct ScreenState;
 
@@ -48,7 +84,8 @@ impl Drop for ScreenState {
     }
 }
 
-#[derive(PartialEq, Default, Debug, Clone, Copy)]
+#[derive(PartialEq, Default, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 #[repr(usize)]
 enum Status {
     #[default]
@@ -80,13 +117,46 @@ impl BitXor<usize> for Status {
 #[derive(Default)]
 struct App {
     quit: bool,
+    save_requested: bool,
+    // shown in the status bar until the next successful save
+    save_error: Option<String>,
+    // the file was changed by someone else, the next save overwrites it
+    // without asking again
+    changed_on_disk: bool,
+    // shown in the status bar until the next key press
+    message: Option<String>,
     //w: u16,
     //h: u16,
     active_status: Status,
     edit_mode: bool,
-    edit_cursor: usize,
+    // the title being edited, written back to the item on every change
+    editor: LineEditor,
     // at start it is list.len()
     lists: [ItemList; 2],
+    // number of item rows that fit below the column headers
+    viewport_height: usize,
+    next_id: ItemId,
+    // time of the latest change, None once everything is saved
+    changed_at: Option<Instant>,
+    history: History,
+    // the item as it was when edit mode started
+    edit_before: Option<Item>,
+    // where the history is kept between sessions, None to not keep it
+    history_file: Option<PathBuf>,
+    // tags and the due date are words of the title, see set_title()
+    metadata_in_titles: bool,
+    prompt: Option<Prompt>,
+    // matches are highlighted
+    search: Option<Pattern>,
+    // items that don't match are hidden
+    filter: Option<Query>,
+    // saved filters, cycled through with v and V
+    views: Vec<View>,
+    // the match mode used the last time
+    match_mode: MatchMode,
+    keymap: Keymap,
+    // Vim keys instead of the default ones, keys it doesn't use go to `keymap`
+    vim: Option<Vim>,
 }
 
 impl App {
@@ -95,72 +165,406 @@ impl App {
     }
 
     fn cursor_up(&mut self) {
-        self.active_list_mut().cursor_up();
+        let rows = self.rows(self.active_status);
+        self.active_list_mut().cursor_up(&rows);
     }
     fn cursor_down(&mut self) {
-        self.active_list_mut().cursor_down();
+        let rows = self.rows(self.active_status);
+        self.active_list_mut().cursor_down(&rows);
     }
     fn cursor_to_top(&mut self) {
-        self.active_list_mut().cursor_to_top();
+        let rows = self.rows(self.active_status);
+        self.active_list_mut().cursor_to_top(&rows);
     }
     fn cursor_to_bottom(&mut self) {
-        self.active_list_mut().cursor_to_bottom()
+        let rows = self.rows(self.active_status);
+        self.active_list_mut().cursor_to_bottom(&rows)
     }
+    // Unlike cursor_up() and cursor_down() these stop at the ends of the list
+    fn cursor_up_by(&mut self, amount: usize) {
+        let rows = self.rows(self.active_status);
+        self.active_list_mut().cursor_page_up(amount, &rows);
+    }
+    fn cursor_down_by(&mut self, amount: usize) {
+        let rows = self.rows(self.active_status);
+        self.active_list_mut().cursor_page_down(amount, &rows);
+    }
+    fn page_up(&mut self) {
+        self.cursor_up_by(self.viewport_height);
+    }
+    fn page_down(&mut self) {
+        self.cursor_down_by(self.viewport_height);
+    }
+    fn half_page_up(&mut self) {
+        self.cursor_up_by(self.viewport_height / 2);
+    }
+    fn half_page_down(&mut self) {
+        self.cursor_down_by(self.viewport_height / 2);
+    }
+    fn scroll_to_cursor(&mut self) {
+        let height = self.viewport_height;
+        for status in [Status::Todo, Status::Done] {
+            let rows = self.rows(status);
+            self.lists[status as usize].scroll_to_cursor(height, &rows);
+        }
+    }
+    // Dragging passes the hidden items, the item ends up next to the shown one
     fn drag_up(&mut self) {
-        self.active_list_mut().list_drag_up();
+        let rows = self.rows(self.active_status);
+        let from = self.active_cursor();
+        if let Some(&to) = rows.iter().rev().find(|&&index| index < from) {
+            self.active_list_mut().move_item(from, to);
+            self.record(Change::Move {
+                status: self.active_status,
+                from,
+                to,
+            });
+        }
     }
     fn drag_down(&mut self) {
-        self.active_list_mut().list_drag_down();
+        let rows = self.rows(self.active_status);
+        let from = self.active_cursor();
+        if let Some(&to) = rows.iter().find(|&&index| index > from) {
+            self.active_list_mut().move_item(from, to);
+            self.record(Change::Move {
+                status: self.active_status,
+                from,
+                to,
+            });
+        }
     }
 
-    fn edit_add_char(&mut self, c: char) {
-        let cursor = self.active_cursor();
-        let edit_cursor = self.edit_cursor;
-        self.active_items_mut()[cursor].push(c);
-        //let tmp = tmp.chars() + c;
-        //tmp.
-        //self.active_items_mut()[cursor].insert(edit_cursor, c);
-        self.edit_cursor_right();
+    // Indices of the items of the list of `status` that pass the filter
+    fn matching(&self, status: Status) -> Vec<usize> {
+        let items = &self.lists[status as usize].items;
+        let today = item::today();
+        (0..items.len())
+            .filter(|&index| match &self.filter {
+                Some(filter) => filter.matches(status, &items[index], today),
+                None => true,
+            })
+            .collect()
     }
 
-    fn backspace(&mut self) {
-        let cursor = self.active_cursor();
-        let edit_cursor = self.edit_cursor;
-        let mut chars = self.active_items()[cursor].chars();
-        chars.next_back();
-        
-        self.active_items_mut()[cursor] = chars.as_str().to_owned();
+    // Indices of the items shown in the list of `status`. The item under the
+    // cursor stays even if it doesn't pass the filter (anymore), so it doesn't
+    // vanish while it is edited or right after it was added.
+    fn rows(&self, status: Status) -> Vec<usize> {
+        let mut rows = self.matching(status);
+        let list = &self.lists[status as usize];
+        if list.cursor < list.items.len() {
+            if let Err(row) = rows.binary_search(&list.cursor) {
+                rows.insert(row, list.cursor);
+            }
+        }
+        rows
+    }
+
+    fn snap_cursors(&mut self) {
+        for status in [Status::Todo, Status::Done] {
+            let matching = self.matching(status);
+            self.lists[status as usize].snap_cursor(&matching);
+        }
+    }
 
-        //let len = UnicodeSegmentation::graphemes(tmp, true).count();
-        self.edit_cursor_left();    
+    fn start_prompt(&mut self, kind: PromptKind) {
+        // The filter is refined rather than typed again
+        let text = match (kind, &self.filter) {
+            (PromptKind::Filter, Some(filter)) => filter.text.clone(),
+            _ => String::new(),
+        };
+        self.prompt = Some(Prompt {
+            kind,
+            text,
+            mode: self.match_mode,
+            error: None,
+            origin: (self.active_status, self.active_cursor()),
+            previous: self.search.clone(),
+            previous_filter: self.filter.clone(),
+        });
+        self.prompt_changed();
     }
 
-    fn edit_cursor_left(&mut self) {
-        if self.edit_cursor > 0 {
-            self.edit_cursor -= 1;
+    fn prompt_add_char(&mut self, c: char) {
+        if let Some(prompt) = &mut self.prompt {
+            prompt.text.push(c);
         }
+        self.prompt_changed();
     }
-    fn edit_cursor_right(&mut self) {
-        let cursor = self.active_cursor();
-        let tmp = self.active_items()[cursor].as_str();
-        let len = UnicodeSegmentation::graphemes(tmp, true).count();
-        if self.edit_cursor < len {
-            self.edit_cursor += 1;
+
+    fn prompt_backspace(&mut self) {
+        if let Some(prompt) = &mut self.prompt {
+            prompt.text.pop();
+        }
+        self.prompt_changed();
+    }
+
+    // A filter is a query, the match mode only applies to the search
+    fn prompt_next_mode(&mut self) {
+        let search = self.prompt.as_mut().filter(|prompt| prompt.kind == PromptKind::Search);
+        if let Some(prompt) = search {
+            prompt.mode = prompt.mode.next();
+            self.match_mode = prompt.mode;
+        }
+        self.prompt_changed();
+    }
+
+    // Searches and filters as you type
+    fn prompt_changed(&mut self) {
+        let Some(prompt) = &mut self.prompt else {
+            return;
+        };
+        let origin = prompt.origin;
+        if prompt.kind == PromptKind::Filter {
+            let filter = if prompt.text.trim().is_empty() {
+                Ok(None)
+            } else {
+                Query::parse(&prompt.text).map(Some)
+            };
+            match filter {
+                Ok(filter) => {
+                    prompt.error = None;
+                    self.filter = filter;
+                    self.snap_cursors();
+                }
+                // Keep the last working filter while the query is incomplete
+                Err(error) => prompt.error = Some(error),
+            }
+            return;
+        }
+        let pattern = if prompt.text.is_empty() {
+            Ok(None)
+        } else {
+            Pattern::new(&prompt.text, prompt.mode).map(Some)
+        };
+        let pattern = match pattern {
+            Ok(pattern) => {
+                prompt.error = None;
+                pattern
+            }
+            // Keep the last working pattern while the regex is incomplete
+            Err(error) => {
+                prompt.error = Some(error);
+                return;
+            }
+        };
+        self.search = pattern;
+        self.active_status = origin.0;
+        self.lists[origin.0 as usize].cursor = origin.1;
+        if self.search.is_some() {
+            self.search_next(true, true);
+        }
+    }
+
+    fn finish_prompt(&mut self, accept: bool) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+        if accept {
+            return;
+        }
+        match prompt.kind {
+            PromptKind::Search => self.search = prompt.previous,
+            PromptKind::Filter => self.filter = prompt.previous_filter,
+        }
+        self.active_status = prompt.origin.0;
+        self.lists[prompt.origin.0 as usize].cursor = prompt.origin.1;
+    }
+
+    // Moves the cursor to the next (or previous) shown item matching the
+    // search, through both lists. `here` includes the item under the cursor.
+    fn search_next(&mut self, forward: bool, here: bool) {
+        let Some(search) = &self.search else {
+            self.message = Some("No search, / to search".to_string());
+            return;
+        };
+        let mut found = Vec::new();
+        for status in [Status::Todo, Status::Done] {
+            for index in self.rows(status) {
+                if search.is_match(&display_item(&self.lists[status as usize].items[index])) {
+                    found.push((status as usize, index));
+                }
+            }
+        }
+        let current = (self.active_status as usize, self.active_cursor());
+        let next = if forward {
+            found
+                .iter()
+                .find(|&&position| position > current || here && position == current)
+                .or(found.first())
+        } else {
+            found
+                .iter()
+                .rev()
+                .find(|&&position| position < current)
+                .or(found.last())
+        };
+        match next {
+            Some(&(status, index)) => {
+                let wrapped = if forward {
+                    (status, index) < current
+                } else {
+                    (status, index) > current
+                };
+                if wrapped {
+                    self.message = Some("Search wrapped".to_string());
+                }
+                self.active_status = if status == 0 { Status::Todo } else { Status::Done };
+                self.active_list_mut().cursor = index;
+            }
+            None => self.message = Some("No match".to_string()),
+        }
+    }
+
+    // Clears the search highlights or else the filter
+    fn clear_search(&mut self) {
+        if self.search.take().is_none() {
+            self.filter = None;
+        }
+    }
+
+    // Switches the filter to the next (or previous) view, wrapping around
+    // through no filter at all
+    fn cycle_view(&mut self, forward: bool) {
+        if self.views.is_empty() {
+            self.message = Some("No views, see views.toml".to_string());
+            return;
+        }
+        let count = self.views.len() + 1;
+        let current = self.view_index().map_or(0, |index| index + 1);
+        let next = if forward {
+            (current + 1) % count
+        } else {
+            (current + count - 1) % count
+        };
+        self.filter = next.checked_sub(1).map(|index| self.views[index].query.clone());
+        self.snap_cursors();
+    }
+
+    // The view the filter was taken from, if it wasn't changed since
+    fn view_index(&self) -> Option<usize> {
+        let filter = self.filter.as_ref()?;
+        self.views
+            .iter()
+            .position(|view| view.query.text == filter.text)
+    }
+
+    fn mark_changed(&mut self) {
+        self.changed_at = Some(Instant::now());
+    }
+
+    // Every change of the lists goes through here so it can be undone
+    fn record(&mut self, change: Change) {
+        self.history.record(change);
+        self.mark_changed();
+    }
+
+    // Runs `changes` as one undoable step, e.g. the deletions of 3dd
+    fn batch(&mut self, changes: impl FnOnce(&mut App)) {
+        let recorded = self.history.recorded();
+        changes(self);
+        self.history.group(self.history.recorded() - recorded);
+    }
+
+    // The error shown in the status bar, a failed save comes first
+    fn status_error(&self) -> Option<&str> {
+        match &self.save_error {
+            Some(error) => Some(error),
+            None if self.changed_on_disk => {
+                Some("File changed on disk, Ctrl-s or q again overwrites it")
+            }
+            None => None,
+        }
+    }
+
+    fn undo(&mut self) {
+        match self.history.undo() {
+            Some(change) => {
+                let (status, index) = change.revert(&mut self.lists);
+                self.show_change(status, index, "Undone");
+            }
+            None => self.message = Some("Nothing to undo".to_string()),
         }
     }
 
-    fn edit_cursor_begin(&mut self) {
-        self.edit_cursor = 0;
+    fn redo(&mut self) {
+        match self.history.redo() {
+            Some(change) => {
+                let (status, index) = change.apply(&mut self.lists);
+                self.show_change(status, index, "Redone");
+            }
+            None => self.message = Some("Nothing to redo".to_string()),
+        }
+    }
+
+    fn show_change(&mut self, status: Status, index: usize, message: &str) {
+        self.active_status = status;
+        let last = self.active_items().len().saturating_sub(1);
+        self.active_list_mut().cursor = cmp::min(index, last);
+        self.message = Some(message.to_string());
+        self.mark_changed();
     }
 
-    fn edit_cursor_end(&mut self) {
+    // Changes the item at `index` of the list of `status` in one undoable step
+    fn update_item(&mut self, status: Status, index: usize, update: impl FnOnce(&mut Item)) {
+        let before = self.lists[status as usize].items[index].clone();
+        let item = &mut self.lists[status as usize].items[index];
+        update(item);
+        if *item != before {
+            let after = item.clone();
+            self.record(Change::Update {
+                status,
+                index,
+                before,
+                after,
+            });
+        }
+    }
+
+    fn edit_add_char(&mut self, c: char) {
+        self.edit(|editor| editor.insert(c));
+    }
+
+    fn backspace(&mut self) {
+        self.edit(LineEditor::backspace);
+    }
+
+    // Runs an editor command and writes the title back if it changed
+    fn edit(&mut self, command: impl FnOnce(&mut LineEditor)) {
+        command(&mut self.editor);
         let cursor = self.active_cursor();
-        self.edit_cursor = self.active_items()[cursor].len();
+        if self.active_items()[cursor].title != self.editor.text() {
+            let title = self.editor.text().to_string();
+            let metadata_in_titles = self.metadata_in_titles;
+            set_title(&mut self.active_items_mut()[cursor], &title, metadata_in_titles);
+            self.mark_changed();
+        }
     }
 
     fn set_edit(&mut self, edit_active: bool) {
         if !self.edit_mode && edit_active {
-            self.edit_cursor_end();
+            let title = self.active_items()[self.active_cursor()].title.clone();
+            self.editor.reset(&title);
+            self.edit_before = Some(self.active_items()[self.active_cursor()].clone());
+        }
+        // All the typing is undone at once
+        if self.edit_mode && !edit_active {
+            let index = self.active_cursor();
+            let after = self.active_items()[index].clone();
+            if let Some(vim) = &mut self.vim {
+                vim.insert_finished(&after.title);
+            }
+            if let Some(before) = self.edit_before.take().filter(|before| *before != after) {
+                let change = Change::Update {
+                    status: self.active_status,
+                    index,
+                    before,
+                    after,
+                };
+                if !self.history.amend(&change) {
+                    self.history.record(change);
+                }
+            }
         }
         self.edit_mode = edit_active;
     }
@@ -170,22 +574,40 @@ impl App {
         let active_cursor = self.active_list().cursor;
 
         if active_cursor < self.active_items().len() {
-            let tmp = self.active_items_mut().remove(active_cursor);
-            self.lists[(active_list ^ 1) as usize].items.push(tmp);
+            let mut tmp = self.active_items_mut().remove(active_cursor);
+            let before = tmp.clone();
+            match active_list {
+                Status::Todo => tmp.complete(),
+                Status::Done => tmp.uncomplete(),
+            }
+            self.lists[(active_list ^ 1) as usize].items.push(tmp.clone());
             if active_cursor >= self.active_items().len() && !self.active_items().is_empty() {
                 self.active_list_mut().cursor -= 1;
             }
+            self.record(Change::Transfer {
+                from: active_list,
+                index: active_cursor,
+                before,
+                after: tmp,
+            });
+            self.snap_cursors();
         }
     }
 
     fn list_delete(&mut self) {
-        let active_cursor = self.active_cursor().clone();
+        let active_cursor = self.active_cursor();
         if self.active_cursor() < self.active_items().len() {
-            self.active_items_mut().remove(active_cursor);
+            let item = self.active_items_mut().remove(active_cursor);
             if self.active_cursor() >= self.active_items().len() && !self.active_items().is_empty()
             {
                 self.active_list_mut().cursor -= 1;
             }
+            self.record(Change::Remove {
+                status: self.active_status,
+                index: active_cursor,
+                item,
+            });
+            self.snap_cursors();
         }
     }
 
@@ -208,55 +630,107 @@ impl App {
     }
 
     fn new_item(&mut self) {
+        self.insert_item(Item::new(""));
+    }
+
+    // Inserts `item` with a fresh id at the cursor
+    fn insert_item(&mut self, mut item: Item) {
         let active_cursor = self.active_cursor();
-        self.active_items_mut().insert(active_cursor, String::new());
+        item.id = self.alloc_id();
+        self.active_items_mut().insert(active_cursor, item.clone());
+        self.record(Change::Insert {
+            status: self.active_status,
+            index: active_cursor,
+            item,
+        });
     }
 
-    fn load_state(&mut self, file_path: &str) -> Result<()> {
-        let file = File::open(file_path)?;
-        for (index, line) in io::BufReader::new(file).lines().enumerate() {
-            let line: String = line?.as_str().trim().to_string();
+    fn alloc_id(&mut self) -> ItemId {
+        self.next_id += 1;
+        self.next_id
+    }
 
-            if line.is_empty() {
-                continue;
+    // Gives every item without an id (e.g. loaded from an old file) a fresh one
+    fn assign_ids(&mut self) {
+        let max_id = self
+            .lists
+            .iter()
+            .flat_map(|list| list.items.iter())
+            .map(|item| item.id)
+            .max()
+            .unwrap_or(0);
+        self.next_id = cmp::max(self.next_id, max_id);
+        for status in [Status::Todo, Status::Done] {
+            for index in 0..self.lists[status as usize].items.len() {
+                if self.lists[status as usize].items[index].id == 0 {
+                    let id = self.alloc_id();
+                    self.lists[status as usize].items[index].id = id;
+                }
             }
+        }
+    }
 
-            match parse_item(line.as_str()) {
-                Some((Status::Todo, title)) => self.lists[Status::Todo as usize]
-                    .items
-                    .push(title.trim_end().to_string()),
-                Some((Status::Done, title)) => self.lists[Status::Done as usize]
+    // `recover` decides what happens to lines that couldn't be parsed
+    fn load_state(
+        &mut self,
+        storage: &mut dyn Storage,
+        recover: impl FnOnce(&[ParseError]) -> Recovery,
+    ) -> Result<()> {
+        let loaded = storage.load()?;
+        self.metadata_in_titles = storage.metadata_in_titles();
+        let recovery = if loaded.errors.is_empty() {
+            Recovery::Skip
+        } else {
+            recover(&loaded.errors)
+        };
+        if recovery == Recovery::Abort {
+            return Err(ParseErrors(loaded.errors).into());
+        }
+
+        for (status, item) in loaded.items {
+            self.lists[status as usize].items.push(item);
+        }
+        if recovery == Recovery::Import {
+            for error in loaded.errors.iter() {
+                self.lists[Status::Todo as usize]
                     .items
-                    .push(title.trim_end().to_string()),
-                None => {
-                    eprintln!("{}:{}: ERROR: ill-formed item line", file_path, index + 1);
-                    process::exit(1);
-                }
+                    .push(Item::new(error.text.trim()));
+            }
+            storage.forget_errors();
+        }
+        self.assign_ids();
+        if let Some(path) = &self.history_file {
+            let depth = self.history.depth;
+            if let Some(history) = History::load(path, &self.item_lists(), depth) {
+                self.history = history;
             }
         }
         Ok(())
     }
 
-    fn save_state(&mut self, file_path: &str) -> Result<()> {
-        let mut file = File::create(file_path)?;
-        for (index, line) in self.lists[Status::Todo as usize].items.iter().enumerate() {
-            file.write(b"TODO: ")?;
-            file.write(line.as_bytes())?;
-            file.write(b"\n")?;
-        }
-        for (index, line) in self.lists[Status::Done as usize].items.iter().enumerate() {
-            file.write(b"DONE: ")?;
-            file.write(line.as_bytes())?;
-            file.write(b"\n")?;
+    fn save_state(&self, storage: &mut dyn Storage) -> Result<()> {
+        storage.save(&self.item_lists())?;
+        if let Some(path) = &self.history_file {
+            // The list itself is saved, losing the history is not worth an error
+            let _ = self.history.save(path, &self.item_lists());
         }
         Ok(())
     }
+
+    fn item_lists(&self) -> [(Status, &[Item]); 2] {
+        [
+            (Status::Todo, &self.lists[Status::Todo as usize].items),
+            (Status::Done, &self.lists[Status::Done as usize].items),
+        ]
+    }
 }
 
 #[derive(Default)]
 struct ItemList {
     items: Vec<Item>,
     cursor: usize,
+    // index of the first item shown in the viewport
+    scroll: usize,
 }
 
 impl ItemList {
@@ -264,153 +738,479 @@ impl ItemList {
         Self::default()
     }
 
-    fn list_drag_up(&mut self) {
-        if self.cursor > 0 {
-            self.items.swap(self.cursor, self.cursor - 1);
-            self.cursor -= 1;
+    // The cursor always refers to an item by its index in `items`. The
+    // navigation only goes through `rows`, the sorted indices of the items
+    // that are shown, so hidden items are skipped.
+
+    fn move_item(&mut self, from: usize, to: usize) {
+        let item = self.items.remove(from);
+        self.items.insert(to, item);
+        self.cursor = to;
+    }
+
+    // Position of the cursor in `rows`, or of the first row after it if the
+    // cursor item isn't shown
+    fn row(&self, rows: &[usize]) -> usize {
+        rows.partition_point(|&index| index < self.cursor)
+    }
+
+    fn cursor_up(&mut self, rows: &[usize]) {
+        let previous = rows.iter().rev().find(|&&index| index < self.cursor);
+        if let Some(&index) = previous.or(rows.last()) {
+            self.cursor = index;
         }
     }
 
-    fn list_drag_down(&mut self) {
-        if self.cursor + 1 < self.items.len() {
-            self.items.swap(self.cursor, self.cursor + 1);
-            self.cursor += 1;
+    fn cursor_down(&mut self, rows: &[usize]) {
+        let next = rows.iter().find(|&&index| index > self.cursor);
+        if let Some(&index) = next.or(rows.first()) {
+            self.cursor = index;
         }
     }
 
-    fn cursor_up(&mut self) {
-        if self.cursor > 0 {
-            self.cursor -= 1
-        } else {
-            self.cursor = self.items.len() - 1;
+    fn cursor_to_top(&mut self, rows: &[usize]) {
+        if let Some(&index) = rows.first() {
+            self.cursor = index;
         }
     }
 
-    fn cursor_down(&mut self) {
-        if self.cursor + 1 < self.items.len() {
-            self.cursor += 1;
-        } else {
-            self.cursor = 0;
+    fn cursor_to_bottom(&mut self, rows: &[usize]) {
+        if let Some(&index) = rows.last() {
+            self.cursor = index;
         }
     }
 
-    fn cursor_to_top(&mut self) {
-        self.cursor = 0;
+    fn cursor_page_up(&mut self, amount: usize, rows: &[usize]) {
+        let row = self.row(rows).saturating_sub(amount.max(1));
+        if let Some(&index) = rows.get(row) {
+            self.cursor = index;
+        }
     }
 
-    fn cursor_to_bottom(&mut self) {
-        if !self.items.is_empty() {
-            self.cursor = self.items.len() - 1;
+    fn cursor_page_down(&mut self, amount: usize, rows: &[usize]) {
+        if !rows.is_empty() {
+            let row = cmp::min(self.row(rows) + amount.max(1), rows.len() - 1);
+            self.cursor = rows[row];
         }
     }
+
+    // Moves the cursor to the closest shown item if its own one isn't
+    fn snap_cursor(&mut self, rows: &[usize]) {
+        if !rows.contains(&self.cursor) {
+            let next = rows.iter().find(|&&index| index > self.cursor);
+            if let Some(&index) = next.or(rows.last()) {
+                self.cursor = index;
+            }
+        }
+    }
+
+    // Adjusts `scroll` so that the cursor is inside a viewport of `height` rows.
+    fn scroll_to_cursor(&mut self, height: usize, rows: &[usize]) {
+        let row = self.row(rows);
+        if height == 0 {
+            self.scroll = row;
+            return;
+        }
+        if row < self.scroll {
+            self.scroll = row;
+        } else if row >= self.scroll + height {
+            self.scroll = row + 1 - height;
+        }
+        // Don't leave empty rows at the bottom when the list shrank
+        self.scroll = cmp::min(self.scroll, rows.len().saturating_sub(height));
+    }
+
+    fn has_more_above(&self) -> bool {
+        self.scroll > 0
+    }
+
+    fn has_more_below(&self, height: usize, rows: &[usize]) -> bool {
+        self.scroll + height < rows.len()
+    }
+
+    fn visible<'a>(
+        &'a self,
+        height: usize,
+        rows: &'a [usize],
+    ) -> impl Iterator<Item = (usize, &'a Item)> + 'a {
+        rows.iter()
+            .skip(self.scroll)
+            .take(height)
+            .map(|&index| (index, &self.items[index]))
+    }
+}
+
+// Title with the metadata worth showing in a list row, e.g. "(A) Buy milk #home due:2024-03-01"
+// Changes the title of `item`. In todo.txt lists the tags and the due date are
+// written in the title and follow it.
+fn set_title(item: &mut Item, title: &str, metadata_in_titles: bool) {
+    if metadata_in_titles {
+        storage::todotxt::retitle(item, title);
+    } else {
+        item.title = title.to_string();
+    }
 }
 
-fn parse_item(line: &str) -> Option<(Status, &str)> {
-    let todo_item = line
-        .strip_prefix("TODO: ")
-        .map(|title| (Status::Todo, title));
-    let done_item = line
-        .strip_prefix("DONE: ")
-        .map(|title| (Status::Done, title));
-    todo_item.or(done_item)
+fn display_item(item: &Item) -> String {
+    let mut text = String::new();
+    if let Some(priority) = item.priority {
+        text.push_str(&format!("({}) ", priority));
+    }
+    text.push_str(&item.title);
+    for tag in item.tags.iter() {
+        // todo.txt items already spell their tags out in the title
+        let token = storage::todotxt::tag_token(tag);
+        if !item.title.split(' ').any(|word| word == token) {
+            text.push_str(&format!(" #{}", tag));
+        }
+    }
+    // todo.txt items spell their due date out in the title too
+    let due = item.due.filter(|_| storage::todotxt::due_token(&item.title).is_none());
+    if let Some(due) = due {
+        text.push_str(&format!(" due:{}", due.format(item::DATE_FORMAT)));
+    }
+    if !item.notes.is_empty() {
+        text.push_str(" [+]");
+    }
+    text
+}
+
+fn usage() {
+    eprintln!("Usage: todo-rs [OPTIONS] [<file-path>] [COMMAND]");
+    eprintln!("       todo-rs [OPTIONS] --file <file-path> [COMMAND]");
+    eprintln!("Without a command the list is opened in the terminal UI. Without a");
+    eprintln!("<file-path> the `file` of the config is opened, e.g. from a .todo-rs.toml");
+    eprintln!("in the current directory or above.");
+    eprintln!("OPTIONS:");
+    eprintln!("    --file <file-path>     the list to open");
+    eprintln!("    --format <format>      native, todotxt, markdown or json (default: detected)");
+    eprintln!("    --backups <count>      number of rotating backups to keep (default: 1)");
+    eprintln!("    --autosave <seconds>   save unsaved changes at least this often (default: 30)");
+    eprintln!("    --no-autosave          only save on Ctrl-s and on exit");
+    eprintln!("    --strict               fail on ill-formed lines instead of asking what to do");
+    eprintln!("    --undo-depth <count>   number of changes that can be undone (default: 100)");
+    eprintln!("    --undo-file            keep the undo history in <file-path>.history");
+    eprintln!("    --no-undo-file         don't keep it (default)");
+    eprintln!("    --config <file>        global config");
+    eprintln!("                           (default: $XDG_CONFIG_HOME/todo-rs/config.toml)");
+    eprintln!("    --theme <name>         colour scheme: dark, light or high-contrast");
+    eprintln!("    --color <when>         auto, always or never (default: auto, which honours");
+    eprintln!("                           NO_COLOR, COLORTERM and TERM)");
+    eprintln!("    --keymap <name>        default or vim");
+    eprintln!("    --vim                  same as --keymap vim");
+    eprintln!("    --views <file>         saved queries");
+    eprintln!("                           (default: $XDG_CONFIG_HOME/todo-rs/views.toml)");
+    cli::usage();
+}
+
+fn usage_error(message: &str) -> ! {
+    usage();
+    eprintln!("ERROR: {}", message);
+    process::exit(cli::EXIT_USAGE);
+}
+
+// Options given on the command line, None where the config decides
+struct Args {
+    file_path: Option<String>,
+    format: Option<Format>,
+    // rotating backups kept next to the file on every save
+    backups: Option<usize>,
+    autosave: Option<bool>,
+    autosave_interval: Option<Duration>,
+    theme: Option<String>,
+    color: ColorMode,
+    keymap: Option<KeymapKind>,
+    // replaces the global config file
+    config_file: Option<PathBuf>,
+    strict: bool,
+    undo_depth: Option<usize>,
+    undo_file: Option<bool>,
+    views_file: Option<PathBuf>,
+    command: Option<cli::Command>,
+}
+
+impl Args {
+    // Command line options win over every other source
+    fn apply(&self, config: &mut Config) {
+        if let Some(path) = &self.file_path {
+            config.file = Some(PathBuf::from(path));
+        }
+        if self.format.is_some() {
+            config.format = self.format;
+        }
+        if let Some(backups) = self.backups {
+            config.backups = backups;
+        }
+        if let Some(autosave) = self.autosave {
+            config.autosave = autosave;
+        }
+        if let Some(interval) = self.autosave_interval {
+            config.autosave_interval = interval;
+        }
+        if let Some(theme) = &self.theme {
+            config.theme = theme.clone();
+        }
+        if let Some(keymap) = self.keymap {
+            config.keymap = keymap;
+        }
+        if let Some(undo_depth) = self.undo_depth {
+            config.undo_depth = undo_depth;
+        }
+        if let Some(undo_file) = self.undo_file {
+            config.undo_file = undo_file;
+        }
+    }
 }
 
-fn get_file_argument(file_path: &mut String) {
+fn parse_args() -> Args {
+    let mut parsed = Args {
+        file_path: None,
+        format: None,
+        backups: None,
+        autosave: None,
+        autosave_interval: None,
+        theme: None,
+        color: ColorMode::Auto,
+        keymap: None,
+        config_file: None,
+        strict: false,
+        undo_depth: None,
+        undo_file: None,
+        views_file: views::default_path(),
+        command: None,
+    };
     let mut args = env::args();
     args.next().unwrap();
-    *file_path = match args.next() {
-        Some(file_path) => file_path,
-        None => {
-            eprintln!("Usage: todo-rs <file-path>");
-            eprintln!("ERROR: file path is not provided");
-            process::exit(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--file" => parsed.file_path = Some(value(&mut args, &arg)),
+            "--format" => {
+                let name = value(&mut args, &arg);
+                parsed.format = match Format::from_name(&name) {
+                    Some(format) => Some(format),
+                    None => usage_error(&format!("unknown format `{}`", name)),
+                };
+            }
+            "--backups" => {
+                let count = value(&mut args, &arg);
+                parsed.backups = match count.parse() {
+                    Ok(count) => Some(count),
+                    Err(_) => usage_error(&format!("invalid backup count `{}`", count)),
+                };
+            }
+            "--autosave" => {
+                let seconds = value(&mut args, &arg);
+                parsed.autosave_interval = match seconds.parse() {
+                    Ok(seconds) if seconds > 0 => Some(Duration::from_secs(seconds)),
+                    _ => usage_error(&format!("invalid autosave interval `{}`", seconds)),
+                };
+                parsed.autosave = Some(true);
+            }
+            "--no-autosave" => parsed.autosave = Some(false),
+            "--strict" => parsed.strict = true,
+            "--undo-depth" => {
+                let count = value(&mut args, &arg);
+                parsed.undo_depth = match count.parse() {
+                    Ok(count) => Some(count),
+                    Err(_) => usage_error(&format!("invalid undo depth `{}`", count)),
+                };
+            }
+            "--undo-file" => parsed.undo_file = Some(true),
+            "--no-undo-file" => parsed.undo_file = Some(false),
+            "--views" => parsed.views_file = Some(PathBuf::from(value(&mut args, &arg))),
+            "--theme" => parsed.theme = Some(value(&mut args, &arg)),
+            "--color" => parsed.color = parse_color_mode(&value(&mut args, &arg)),
+            _ if arg.starts_with("--color=") => {
+                parsed.color = parse_color_mode(&arg["--color=".len()..])
+            }
+            "--keymap" => {
+                let name = value(&mut args, &arg);
+                parsed.keymap = match KeymapKind::from_name(&name) {
+                    Some(keymap) => Some(keymap),
+                    None => usage_error(&format!("unknown keymap `{}`", name)),
+                };
+            }
+            "--vim" => parsed.keymap = Some(KeymapKind::Vim),
+            "--config" => parsed.config_file = Some(PathBuf::from(value(&mut args, &arg))),
+            "-h" | "--help" => {
+                usage();
+                process::exit(cli::EXIT_OK);
+            }
+            // Everything after the command name belongs to the command
+            _ if cli::COMMANDS.contains(&arg.as_str()) => {
+                let rest: Vec<String> = args.by_ref().collect();
+                parsed.command = match cli::Command::parse(&arg, &rest) {
+                    Ok(command) => Some(command),
+                    Err(message) => usage_error(&message),
+                };
+            }
+            _ if arg.starts_with("--") => usage_error(&format!("unknown option `{}`", arg)),
+            _ if parsed.file_path.is_none() => parsed.file_path = Some(arg),
+            _ => usage_error(&format!("unknown command `{}`", arg)),
         }
-    };
+    }
+    parsed
 }
 
-fn poll_events(app: &mut App, ui: &mut ui::Ui) -> Result<()> {
-    while poll(Duration::from_millis(10))? {
-        match read()? {
+// The value following `option`
+fn value(args: &mut impl Iterator<Item = String>, option: &str) -> String {
+    match args.next() {
+        Some(value) => value,
+        None => usage_error(&format!("`{}` needs a value", option)),
+    }
+}
+
+fn parse_color_mode(name: &str) -> ColorMode {
+    match ColorMode::from_name(name) {
+        Some(mode) => mode,
+        None => usage_error(&format!("unknown color mode `{}`", name)),
+    }
+}
+
+// Lists the ill-formed lines and asks the user what to do with them. Without
+// a terminal to ask there is nobody to confirm, so loading is aborted.
+fn ask_recovery(errors: &[ParseError]) -> Recovery {
+    const SHOWN: usize = 10;
+    for error in errors.iter().take(SHOWN) {
+        eprintln!("{}", error);
+    }
+    if errors.len() > SHOWN {
+        eprintln!("... and {} more", errors.len() - SHOWN);
+    }
+    if !io::stdin().is_terminal() {
+        return Recovery::Abort;
+    }
+    loop {
+        eprint!("[a]bort, [s]kip and keep the lines as they are, or [i]mport them as TODO items? ");
+        let mut answer = String::new();
+        match io::stdin().read_line(&mut answer) {
+            Ok(0) | Err(_) => return Recovery::Abort,
+            Ok(_) => {}
+        }
+        match answer.trim() {
+            "a" | "abort" => return Recovery::Abort,
+            "s" | "skip" => return Recovery::Skip,
+            "i" | "import" => return Recovery::Import,
+            _ => {}
+        }
+    }
+}
+
+// One pass of the UI loop: handles the events that came in, saves if asked
+// to or if autosave is due, and draws a frame. False once the app quit.
+fn step(
+    app: &mut App,
+    ui: &mut ui::Ui<impl Backend>,
+    events: &mut impl EventSource,
+    storage: &mut dyn Storage,
+    autosave: &mut Autosave,
+    theme: &Theme,
+) -> Result<bool> {
+    poll_events(app, ui, events)?;
+
+    let manual_save = app.save_requested;
+    autosave.track(app.changed_at);
+    if !manual_save && !app.quit && autosave.is_due(app.changed_at, app.edit_mode) {
+        autosave.attempted();
+        // Autosave stays paused until the user decides to overwrite
+        if !app.changed_on_disk {
+            app.save_requested = true;
+        }
+    }
+
+    // Quitting with everything saved leaves the file alone
+    if app.quit && app.changed_at.is_none() {
+        return Ok(false);
+    }
+    // Don't overwrite what someone else wrote to the file in the meantime
+    // without asking, the next save or quit confirms
+    if (app.save_requested || app.quit) && !app.changed_on_disk && storage.watch().unwrap_or(false)
+    {
+        app.changed_on_disk = true;
+        app.save_requested = false;
+        app.quit = false;
+    }
+
+    if app.save_requested || app.quit {
+        app.save_requested = false;
+        match app.save_state(storage) {
+            Ok(()) => {
+                app.save_error = None;
+                app.changed_on_disk = false;
+                app.changed_at = None;
+                if app.quit {
+                    return Ok(false);
+                }
+                if manual_save {
+                    app.message = Some("Saved".to_string());
+                }
+            }
+            // The user was already told the last save failed and wants out anyway
+            Err(err) if app.quit && app.save_error.is_some() => return Err(err),
+            Err(err) => {
+                app.quit = false;
+                app.save_error = Some(format!(
+                    "ERROR: saving failed: {} (press q again to quit without saving)",
+                    err
+                ));
+            }
+        }
+    }
+
+    render::frame(app, ui, theme);
+    Ok(true)
+}
+
+fn poll_events(
+    app: &mut App,
+    ui: &mut ui::Ui<impl Backend>,
+    events: &mut impl EventSource,
+) -> Result<()> {
+    while let Some(event) = events.next(Duration::from_millis(10))? {
+        match event {
             Event::Resize(nw, nh) => {
-                ui.resize(nw as usize, nw as usize);
+                ui.resize(nw as usize, nh as usize);
             }
             Event::Paste(data) => {
                 for c in data.chars() {
-                    app.edit_add_char(c);
+                    if app.prompt.is_some() {
+                        app.prompt_add_char(c);
+                    } else if app.edit_mode {
+                        app.edit_add_char(c);
+                    }
                 }
             }
-            Event::Key(event) => {
-                if event.kind == KeyEventKind::Press {
-                    if app.edit_mode {
-                        match event.code {
-                            KeyCode::Char(x) => {
-                                app.edit_add_char(x);
-                            }
-                            KeyCode::Left => app.edit_cursor_left(),
-                            KeyCode::Right => app.edit_cursor_right(),
-                            KeyCode::Home => app.edit_cursor_begin(),
-                            KeyCode::End => app.edit_cursor_end(),
-                            KeyCode::Backspace => app.backspace(),
-                            KeyCode::Esc | KeyCode::Enter => {
-                                app.set_edit(false);
-                            }
-                            _ => {}
+            Event::Key(event) if event.kind == KeyEventKind::Press => {
+                app.message = None;
+                if app.prompt.is_some() {
+                    match event.code {
+                        KeyCode::Char(x) => app.prompt_add_char(x),
+                        KeyCode::Backspace => app.prompt_backspace(),
+                        KeyCode::Tab => app.prompt_next_mode(),
+                        KeyCode::Enter => app.finish_prompt(true),
+                        KeyCode::Esc => app.finish_prompt(false),
+                        _ => {}
+                    }
+                } else if app.edit_mode {
+                    let lookup = app.keymap.lookup(Mode::Edit, &event);
+                    for key in app.keymap.take_broken() {
+                        if let Some(c) = key.typed_char() {
+                            app.edit_add_char(c);
                         }
-                    } else {
-                        // Not in edit mode
-                        match event.code {
-                            KeyCode::Char(x) => {
-                                if x == 'c' && event.modifiers.contains(KeyModifiers::CONTROL) {
-                                    app.quit = true;
-                                }
-                                else if x == 'q' {
-                                    app.quit = true;
-                                }
-                            }
-                            KeyCode::Esc => {
-                                app.quit = true;
-                            }
-                            KeyCode::Enter => {
-                                if app.active_status == Status::Todo {
-                                    app.set_edit(true)
-                                }
-                            },
-                            KeyCode::Tab => {
-                                app.active_status ^= 1;
-                            }
-                            KeyCode::Up => {
-                                if event.modifiers.contains(KeyModifiers::CONTROL) {
-                                    app.drag_up();
-                                } else {
-                                    app.cursor_up();
-                                }
-                            }
-                            KeyCode::Down => {
-                                if event.modifiers.contains(KeyModifiers::CONTROL) {
-                                    app.drag_down();
-                                } else {
-                                    app.cursor_down();
-                                }
-                            }
-                            KeyCode::Left => {
-                                if app.active_status == Status::Done {
-                                    app.list_transfer();
-                                }
-                            }
-                            KeyCode::Right => {
-                                if app.active_status == Status::Todo {
-                                    app.list_transfer();
-                                }
-                            }
-                            KeyCode::Delete => {
-                                app.list_delete();
-                            }
-                            KeyCode::Insert => {
-                                app.new_item();
+                    }
+                    match lookup {
+                        Lookup::Action(action) => perform(app, action),
+                        Lookup::Pending => {}
+                        Lookup::Unbound => {
+                            if let Some(c) = Key::from_event(&event).typed_char() {
+                                app.edit_add_char(c);
                             }
-                            _ => {}
                         }
                     }
+                } else if vim::handle_key(app, event) {
+                    // Handled by the Vim keymap
+                } else if let Lookup::Action(action) = app.keymap.lookup(Mode::View, &event) {
+                    perform(app, action);
                 }
             }
             _ => {}
@@ -419,81 +1219,149 @@ fn poll_events(app: &mut App, ui: &mut ui::Ui) -> Result<()> {
     Ok(())
 }
 
+fn perform(app: &mut App, action: Action) {
+    match action {
+        Action::Quit => app.quit = true,
+        Action::Save => app.save_requested = true,
+        Action::CursorUp => app.cursor_up(),
+        Action::CursorDown => app.cursor_down(),
+        Action::Top => app.cursor_to_top(),
+        Action::Bottom => app.cursor_to_bottom(),
+        Action::PageUp => app.page_up(),
+        Action::PageDown => app.page_down(),
+        Action::HalfPageUp => app.half_page_up(),
+        Action::HalfPageDown => app.half_page_down(),
+        Action::DragUp => app.drag_up(),
+        Action::DragDown => app.drag_down(),
+        Action::SwitchList => app.active_status ^= 1,
+        Action::Transfer => app.list_transfer(),
+        Action::MarkTodo if app.active_status == Status::Done => app.list_transfer(),
+        Action::MarkDone if app.active_status == Status::Todo => app.list_transfer(),
+        Action::MarkTodo | Action::MarkDone => {}
+        Action::Delete if !app.active_items().is_empty() => {
+            app.list_delete();
+            app.message = Some("Deleted, u to undo".to_string());
+        }
+        Action::Delete => {}
+        Action::NewItem => app.new_item(),
+        // Only TODO items are shown with the editor
+        Action::Edit if app.active_status == Status::Todo && !app.active_items().is_empty() => {
+            app.set_edit(true)
+        }
+        Action::Edit => {}
+        Action::Undo => app.undo(),
+        Action::Redo => app.redo(),
+        Action::Search => app.start_prompt(PromptKind::Search),
+        Action::Filter => app.start_prompt(PromptKind::Filter),
+        Action::SearchNext => app.search_next(true, false),
+        Action::SearchPrevious => app.search_next(false, false),
+        Action::NextView => app.cycle_view(true),
+        Action::PreviousView => app.cycle_view(false),
+        Action::ClearOrQuit if app.search.is_some() || app.filter.is_some() => app.clear_search(),
+        Action::ClearOrQuit => app.quit = true,
+        Action::FinishEdit => app.set_edit(false),
+        Action::Left => app.edit(LineEditor::left),
+        Action::Right => app.edit(LineEditor::right),
+        Action::Home => app.edit(LineEditor::home),
+        Action::End => app.edit(LineEditor::end),
+        Action::WordLeft => app.edit(LineEditor::word_left),
+        Action::WordRight => app.edit(LineEditor::word_right),
+        Action::Backspace => app.backspace(),
+        Action::DeleteForward => app.edit(LineEditor::delete),
+        Action::KillWordBefore => app.edit(LineEditor::kill_word_before),
+        Action::KillBigWordBefore => app.edit(LineEditor::kill_big_word_before),
+        Action::KillWordAfter => app.edit(LineEditor::kill_word_after),
+        Action::KillToStart => app.edit(LineEditor::kill_to_start),
+        Action::KillToEnd => app.edit(LineEditor::kill_to_end),
+        Action::Yank => app.edit(LineEditor::yank),
+        Action::YankPop => app.edit(LineEditor::yank_pop),
+        Action::Transpose => app.edit(LineEditor::transpose),
+    }
+}
+
 // https://github.com/tsoding/4at/blob/main/src/client.rs
 
 fn main() -> Result<()> {
     env::set_var("RUST_BACKTRACE", "full");
-    let _screen_state = ScreenState::enable()?;
-    let mut app = App::new();
-    let (w, h) = terminal::size()?;
-
-    let mut file_path = String::new();
-    get_file_argument(&mut file_path);
-    app.load_state(&file_path)?;
+    // Parse arguments before entering raw mode so usage errors stay readable
+    let args = parse_args();
+    let mut config = match Config::load(args.config_file.as_deref()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("ERROR: {:#}", err);
+            process::exit(cli::EXIT_ERROR);
+        }
+    };
+    args.apply(&mut config);
+    let theme = match Theme::build(&config.theme, &config.colors) {
+        Ok(theme) => theme,
+        Err(err) => {
+            eprintln!("ERROR: {:#}", err);
+            process::exit(cli::EXIT_ERROR);
+        }
+    };
+    let Some(file_path) = config.file.as_ref().map(|path| path.to_string_lossy().to_string())
+    else {
+        usage_error("file path is not provided and no config names one");
+    };
 
-    let mut ui = ui::Ui::new(w as usize, h as usize);
-    let mut last_edit = false;
-    while !app.quit {
-        poll_events(&mut app, &mut ui)?;
+    let mut storage = storage::open(&file_path, config.format, config.backups);
+    let mut autosave = Autosave::default();
+    autosave.enabled = config.autosave;
+    autosave.interval = config.autosave_interval;
 
-        if app.edit_mode {
-            if !last_edit {
-                last_edit = true;
-                //let _ =ui.screen.flush(&mut stdout());
+    // Load before entering raw mode as well, ill-formed lines may need a decision
+    let mut app = App::new();
+    app.history.depth = config.undo_depth;
+    if config.undo_file && config.undo_depth > 0 {
+        app.history_file = Some(History::sidecar(file_path.as_ref()));
+    }
+    if let Some(path) = &args.views_file {
+        match views::load(path) {
+            Ok(views) => app.views = views,
+            Err(err) => {
+                eprintln!("ERROR: {:#}", err);
+                process::exit(cli::EXIT_ERROR);
             }
         }
-
-        ui.begin(Vec2::null(), LayoutKind::Vert);
-        {
-            ui.begin_layout(LayoutKind::Horz);
-            {
-                ui.begin_layout(LayoutKind::Vert);
-                {
-                    ui.label_fixed_width("TODO", (w / 2).into(), Color::Cyan, Color::Black);
-                    for (index, todo) in app.lists[Status::Todo as usize].items.iter().enumerate() {
-                        let color = if index == app.active_cursor()
-                            && app.active_status == Status::Todo
-                            && !app.edit_mode
-                        {
-                            (Color::Black, Color::White)
-                        } else {
-                            (Color::White, Color::Black)
-                        };
-                        ui.label_edit(&format!("[ ] {}", todo), color.0, color.1, app.edit_mode && index == app.active_cursor());
-                    }
-                }
-                ui.end_layout();
-                ui.begin_layout(LayoutKind::Vert);
-                {
-                    ui.label_fixed_width("DONE", (w / 2) as i32, Color::Cyan, Color::Black);
-                    for (index, todo) in app.lists[Status::Done as usize].items.iter().enumerate() {
-                        let color = if index == app.active_cursor()
-                            && app.active_status == Status::Done
-                            && !app.edit_mode
-                        {
-                            (Color::Black, Color::White)
-                        } else {
-                            (Color::White, Color::Black)
-                        };
-                        ui.label(&format!("[x] {}", todo), color.0, color.1);
-                    }
-                }
-                ui.end_layout();
-
+    }
+    app.keymap = config.keys.clone();
+    if config.keymap == KeymapKind::Vim {
+        app.vim = Some(Vim::default());
+    }
+    let strict = args.strict;
+    let loaded = app.load_state(storage.as_mut(), |errors| {
+        if strict {
+            for error in errors.iter() {
+                eprintln!("{}", error);
             }
-            ui.end_layout();
+            Recovery::Abort
+        } else {
+            ask_recovery(errors)
         }
+    });
+    if let Err(err) = loaded {
+        if err.is::<ParseErrors>() {
+            eprintln!("ERROR: loading aborted");
+        } else {
+            eprintln!("ERROR: {:#}", err);
+        }
+        process::exit(cli::EXIT_ERROR);
+    }
 
-        let edit_state = if app.edit_mode { "Edit" } else { "View" };
-        let prompt = format!("{}: {:?}", edit_state, app.active_status);
-        let prompt = format!("{:width$}", prompt, width=w as usize);
-        //let prompt = format!("{edit_state}: {:?}", app.active_status);
-        ui.screen.put_cells(0, h as usize, &prompt, Color::Black, Color::White);
-
-        ui.end();
+    if let Some(command) = args.command {
+        process::exit(cli::run(&mut app, storage.as_mut(), command));
     }
 
-    app.save_state(&file_path)?;
+    let _screen_state = ScreenState::enable()?;
+    let (w, h) = terminal::size()?;
+
+    let mut screen = VirtualScreen::new(w as usize, h as usize);
+    screen.set_palette(color::detect(args.color));
+    screen.set_blank(theme.normal.fg, theme.normal.bg);
+    let mut ui = ui::Ui::new(screen, Terminal::stdout());
+    let mut events = TerminalEvents;
+    while step(&mut app, &mut ui, &mut events, storage.as_mut(), &mut autosave, &theme)? {}
 
     Ok(())
 }
@@ -0,0 +1,555 @@
+// Query language to select items, used by the filter bar, views and
+// `list --query`
+//
+//     status:todo tag:backend due<2026-11-01 prio>=B text~"deploy"
+//
+// Terms are `field<op>value` or plain words, which match the text. Terms next
+// to each other must all match, `or` between them needs only one, `not` or a
+// leading `-` negates a term and parentheses group terms.
+//
+//     status:todo|done      tag:name         section:part
+//     text:part             part             "two words"     (ignoring case)
+//     text=Part             (exact case)     text~regex
+//     prio:A  prio>=B  ...  (A is the highest priority)
+//     due<date  created>=date  completed:date   with date YYYY-MM-DD,
+//                                                today, tomorrow, yesterday,
+//                                                +3d, -2w
+//     has:due|prio|tags|notes|section           is:overdue|todo|done
+//     id:12
+
+use std::cmp::Ordering;
+
+use chrono::{Days, NaiveDate};
+
+use crate::item::{self, Item, ItemId};
+use crate::search::{MatchMode, Pattern};
+use crate::Status;
+
+#[derive(Debug, Clone)]
+pub struct Query {
+    pub text: String,
+    expr: Expr,
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let tokens = lex(text)?;
+        let mut parser = Parser { tokens, next: 0 };
+        let expr = if parser.tokens.is_empty() {
+            Expr::And(Vec::new())
+        } else {
+            parser.or()?
+        };
+        if let Some(token) = parser.tokens.get(parser.next) {
+            return Err(format!("unexpected `{}` at column {}", token.text, token.column));
+        }
+        Ok(Self {
+            text: text.to_string(),
+            expr,
+        })
+    }
+
+    // Relative dates like `today` or `+3d` count from `today`, so a query
+    // parsed once still works after midnight
+    pub fn matches(&self, status: Status, item: &Item, today: NaiveDate) -> bool {
+        self.expr.matches(status, item, today)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    // an empty And matches everything
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Term(Term),
+}
+
+#[derive(Debug, Clone)]
+enum Term {
+    Status(Status),
+    Tag(String),
+    Section(String),
+    Text(Pattern),
+    Id(ItemId),
+    Priority(Compare, char),
+    Date(DateField, Compare, QueryDate),
+    Has(Field),
+    Overdue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum QueryDate {
+    On(NaiveDate),
+    // days from today, negative for the past
+    FromToday(i64),
+}
+
+impl QueryDate {
+    // None if the date is out of range
+    fn resolve(self, today: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            QueryDate::On(date) => Some(date),
+            QueryDate::FromToday(days) if days >= 0 => {
+                today.checked_add_days(Days::new(days.unsigned_abs()))
+            }
+            QueryDate::FromToday(days) => today.checked_sub_days(Days::new(days.unsigned_abs())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Compare {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl Compare {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Compare::Lt => ordering.is_lt(),
+            Compare::Le => ordering.is_le(),
+            Compare::Eq => ordering.is_eq(),
+            Compare::Ge => ordering.is_ge(),
+            Compare::Gt => ordering.is_gt(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DateField {
+    Due,
+    Created,
+    Completed,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Due,
+    Priority,
+    Tags,
+    Notes,
+    Section,
+}
+
+impl Expr {
+    fn matches(&self, status: Status, item: &Item, today: NaiveDate) -> bool {
+        match self {
+            Expr::And(exprs) => exprs.iter().all(|expr| expr.matches(status, item, today)),
+            Expr::Or(exprs) => exprs.iter().any(|expr| expr.matches(status, item, today)),
+            Expr::Not(expr) => !expr.matches(status, item, today),
+            Expr::Term(term) => term.matches(status, item, today),
+        }
+    }
+}
+
+impl Term {
+    fn matches(&self, status: Status, item: &Item, today: NaiveDate) -> bool {
+        match self {
+            Term::Status(wanted) => status == *wanted,
+            Term::Tag(tag) => item
+                .tags
+                .iter()
+                .any(|item_tag| bare_tag(item_tag).eq_ignore_ascii_case(tag)),
+            Term::Section(part) => item
+                .section
+                .as_ref()
+                .is_some_and(|section| section.to_lowercase().contains(part)),
+            Term::Text(pattern) => pattern.is_match(&item.title) || pattern.is_match(&item.notes),
+            Term::Id(id) => item.id == *id,
+            // Compared by rank, so prio>=B holds for A and B
+            Term::Priority(compare, priority) => item
+                .priority
+                .is_some_and(|item_priority| compare.holds(priority.cmp(&item_priority))),
+            Term::Date(field, compare, date) => {
+                let item_date = match field {
+                    DateField::Due => item.due,
+                    DateField::Created => item.created.map(|created| created.date()),
+                    DateField::Completed => item.completed.map(|completed| completed.date()),
+                };
+                let date = date.resolve(today);
+                item_date
+                    .zip(date)
+                    .is_some_and(|(item_date, date)| compare.holds(item_date.cmp(&date)))
+            }
+            Term::Has(field) => match field {
+                Field::Due => item.due.is_some(),
+                Field::Priority => item.priority.is_some(),
+                Field::Tags => !item.tags.is_empty(),
+                Field::Notes => !item.notes.is_empty(),
+                Field::Section => item.section.is_some(),
+            },
+            Term::Overdue => status == Status::Todo && item.is_overdue(today),
+        }
+    }
+}
+
+// Tags are written with the marker of their format (#, + or @), `tag:` matches without
+fn bare_tag(tag: &str) -> &str {
+    tag.trim_start_matches(['#', '+', '@'])
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Open,
+    Close,
+    Not,
+    And,
+    Or,
+    // `field<op>value`, the op is kept with the field, e.g. ("due<", "2026-11-01")
+    Field(String, String, String),
+    Word(String),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    // as written, for error messages
+    text: String,
+    column: usize,
+}
+
+const OPERATORS: &[&str] = &["<=", ">=", ":", "=", "<", ">", "~"];
+
+fn lex(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut at = 0;
+    while at < chars.len() {
+        let start = at;
+        let c = chars[at];
+        let kind = if c.is_whitespace() {
+            at += 1;
+            continue;
+        } else if c == '(' {
+            at += 1;
+            TokenKind::Open
+        } else if c == ')' {
+            at += 1;
+            TokenKind::Close
+        } else if c == '-' && chars.get(at + 1).is_some_and(|next| !next.is_whitespace()) {
+            at += 1;
+            TokenKind::Not
+        } else if c == '"' {
+            let (word, end) = quoted(&chars, at)?;
+            at = end;
+            TokenKind::Word(word)
+        } else {
+            let name_end = (at..chars.len())
+                .find(|&index| !chars[index].is_ascii_alphabetic())
+                .unwrap_or(chars.len());
+            let rest: String = chars[name_end..].iter().collect();
+            let operator = OPERATORS.iter().find(|operator| rest.starts_with(**operator));
+            match operator {
+                Some(operator) if name_end > at => {
+                    let name: String = chars[at..name_end].iter().collect();
+                    at = name_end + operator.chars().count();
+                    let value = if chars.get(at) == Some(&'"') {
+                        let (value, end) = quoted(&chars, at)?;
+                        at = end;
+                        value
+                    } else {
+                        let end = word_end(&chars, at);
+                        let value = chars[at..end].iter().collect();
+                        at = end;
+                        value
+                    };
+                    TokenKind::Field(name.to_lowercase(), operator.to_string(), value)
+                }
+                _ => {
+                    let end = word_end(&chars, at);
+                    let word: String = chars[at..end].iter().collect();
+                    at = end;
+                    match word.to_lowercase().as_str() {
+                        "and" => TokenKind::And,
+                        "or" => TokenKind::Or,
+                        "not" => TokenKind::Not,
+                        _ => TokenKind::Word(word),
+                    }
+                }
+            }
+        };
+        tokens.push(Token {
+            kind,
+            text: chars[start..at].iter().collect(),
+            column: start + 1,
+        });
+    }
+    Ok(tokens)
+}
+
+fn word_end(chars: &[char], from: usize) -> usize {
+    (from..chars.len())
+        .find(|&index| chars[index].is_whitespace() || chars[index] == '(' || chars[index] == ')')
+        .unwrap_or(chars.len())
+}
+
+// The text between the quote at `from` and the closing one, and the index after it
+fn quoted(chars: &[char], from: usize) -> Result<(String, usize), String> {
+    match (from + 1..chars.len()).find(|&index| chars[index] == '"') {
+        Some(end) => Ok((chars[from + 1..end].iter().collect(), end + 1)),
+        None => Err(format!("unclosed quote at column {}", from + 1)),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.next).map(|token| &token.kind)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut exprs = vec![self.and()?];
+        while self.peek() == Some(&TokenKind::Or) {
+            self.next += 1;
+            exprs.push(self.and()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::Or(exprs)
+        })
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut exprs = vec![self.unary()?];
+        loop {
+            match self.peek() {
+                Some(TokenKind::And) => self.next += 1,
+                None | Some(TokenKind::Or) | Some(TokenKind::Close) => break,
+                Some(_) => {}
+            }
+            exprs.push(self.unary()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::And(exprs)
+        })
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let Some(token) = self.tokens.get(self.next).cloned() else {
+            return Err("unexpected end of query".to_string());
+        };
+        self.next += 1;
+        match token.kind {
+            TokenKind::Not => Ok(Expr::Not(Box::new(self.unary()?))),
+            TokenKind::Open => {
+                let expr = self.or()?;
+                if self.peek() != Some(&TokenKind::Close) {
+                    return Err(format!("unclosed `(` at column {}", token.column));
+                }
+                self.next += 1;
+                Ok(expr)
+            }
+            TokenKind::Word(word) => Ok(Expr::Term(text_pattern(&word, ":")?)),
+            TokenKind::Field(name, operator, value) => self
+                .term(&name, &operator, &value)
+                .map(Expr::Term)
+                .map_err(|message| format!("{} at column {}", message, token.column)),
+            TokenKind::Close | TokenKind::And | TokenKind::Or => {
+                Err(format!("unexpected `{}` at column {}", token.text, token.column))
+            }
+        }
+    }
+
+    fn term(&self, name: &str, operator: &str, value: &str) -> Result<Term, String> {
+        let equality = operator == ":" || operator == "=";
+        let term = match name {
+            "status" | "is" if equality => match value.to_lowercase().as_str() {
+                "todo" => Term::Status(Status::Todo),
+                "done" => Term::Status(Status::Done),
+                "overdue" if name == "is" => Term::Overdue,
+                _ => return Err(format!("unknown {} `{}`", name, value)),
+            },
+            "tag" | "tags" if equality => Term::Tag(bare_tag(value).to_string()),
+            "section" if equality => Term::Section(value.to_lowercase()),
+            "text" | "title" => text_pattern(value, operator)?,
+            "id" if equality => Term::Id(
+                value
+                    .parse()
+                    .map_err(|_| format!("invalid id `{}`", value))?,
+            ),
+            "prio" | "priority" => {
+                let priority = item::parse_priority(&value.to_uppercase())?;
+                Term::Priority(compare(operator, name)?, priority)
+            }
+            "due" | "created" | "completed" => {
+                let field = match name {
+                    "due" => DateField::Due,
+                    "created" => DateField::Created,
+                    _ => DateField::Completed,
+                };
+                Term::Date(field, compare(operator, name)?, query_date(value)?)
+            }
+            "has" if equality => Term::Has(match value.to_lowercase().as_str() {
+                "due" => Field::Due,
+                "prio" | "priority" => Field::Priority,
+                "tag" | "tags" => Field::Tags,
+                "note" | "notes" => Field::Notes,
+                "section" => Field::Section,
+                _ => return Err(format!("unknown field `{}`", value)),
+            }),
+            "status" | "is" | "tag" | "tags" | "section" | "id" | "has" => {
+                return Err(format!("`{}` expects `:`", name))
+            }
+            _ => return Err(format!("unknown field `{}`", name)),
+        };
+        Ok(term)
+    }
+
+}
+
+// YYYY-MM-DD, today, tomorrow, yesterday or days/weeks from today like +3d or -2w
+fn query_date(value: &str) -> Result<QueryDate, String> {
+    let invalid = || format!("invalid date `{}`", value);
+    match value.to_lowercase().as_str() {
+        "today" => return Ok(QueryDate::FromToday(0)),
+        "tomorrow" => return Ok(QueryDate::FromToday(1)),
+        "yesterday" => return Ok(QueryDate::FromToday(-1)),
+        _ => {}
+    }
+    let Some(sign) = value.chars().next().filter(|c| *c == '+' || *c == '-') else {
+        return item::parse_date(value).map(QueryDate::On);
+    };
+    let mut count = value[1..].to_string();
+    let unit = count.pop();
+    let count: i64 = count.parse().map_err(|_| invalid())?;
+    let days = match unit {
+        Some('d') => Some(count),
+        Some('w') => count.checked_mul(7),
+        _ => return Err(invalid()),
+    };
+    // Further than any two dates can be apart, so never resolvable
+    let span = NaiveDate::MAX.signed_duration_since(NaiveDate::MIN).num_days();
+    let days = days.filter(|days| *days <= span).ok_or_else(invalid)?;
+    Ok(QueryDate::FromToday(if sign == '+' { days } else { -days }))
+}
+
+fn compare(operator: &str, name: &str) -> Result<Compare, String> {
+    match operator {
+        ":" | "=" => Ok(Compare::Eq),
+        "<" => Ok(Compare::Lt),
+        "<=" => Ok(Compare::Le),
+        ">" => Ok(Compare::Gt),
+        ">=" => Ok(Compare::Ge),
+        _ => Err(format!("`{}` can't be used with `{}`", operator, name)),
+    }
+}
+
+fn text_pattern(value: &str, operator: &str) -> Result<Term, String> {
+    let mode = match operator {
+        ":" => MatchMode::IgnoreCase,
+        "=" => MatchMode::Substring,
+        "~" => MatchMode::Regex,
+        _ => return Err(format!("`{}` can't be used with `text`", operator)),
+    };
+    Pattern::new(value, mode).map(Term::Text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(title: &str) -> Item {
+        Item::from_title(title)
+    }
+
+    fn matches(query: &str, status: Status, item: &Item) -> bool {
+        Query::parse(query).unwrap().matches(status, item, item::today())
+    }
+
+    fn date(text: &str) -> NaiveDate {
+        item::parse_date(text).unwrap()
+    }
+
+    // Parses the date `value` as if today was `today`
+    fn date_at(today: &str, value: &str) -> Result<NaiveDate, String> {
+        let resolved = query_date(value)?.resolve(date(today));
+        resolved.ok_or_else(|| format!("`{}` is out of range", value))
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let milk = item("Buy milk");
+        // (buy and bread) or milk
+        assert!(matches("buy bread or milk", Status::Todo, &milk));
+        assert!(!matches("buy (bread or eggs)", Status::Todo, &milk));
+        assert!(matches("buy and (bread or milk)", Status::Todo, &milk));
+        assert!(!matches("status:done and buy or bread", Status::Todo, &milk));
+    }
+
+    #[test]
+    fn negation() {
+        let milk = item("Buy milk");
+        assert!(matches("-bread", Status::Todo, &milk));
+        assert!(!matches("not milk", Status::Todo, &milk));
+        assert!(matches("not (bread or eggs) milk", Status::Todo, &milk));
+        assert!(!matches("-status:todo", Status::Todo, &milk));
+        assert!(matches("not not milk", Status::Todo, &milk));
+    }
+
+    #[test]
+    fn priorities_compare_by_rank() {
+        let ranked = |priority| Item {
+            priority,
+            ..item("Ship it")
+        };
+        assert!(matches("prio>=B", Status::Todo, &ranked(Some('A'))));
+        assert!(matches("prio>=B", Status::Todo, &ranked(Some('B'))));
+        assert!(!matches("prio>=B", Status::Todo, &ranked(Some('C'))));
+        assert!(matches("prio<B", Status::Todo, &ranked(Some('C'))));
+        assert!(matches("prio:a", Status::Todo, &ranked(Some('A'))));
+        assert!(!matches("prio<Z", Status::Todo, &ranked(None)));
+    }
+
+    #[test]
+    fn relative_dates() {
+        assert_eq!(date_at("2024-02-27", "+3d"), Ok(date("2024-03-01")));
+        assert_eq!(date_at("2024-03-01", "-2w"), Ok(date("2024-02-16")));
+        assert_eq!(date_at("2024-12-31", "tomorrow"), Ok(date("2025-01-01")));
+        assert_eq!(date_at("2024-03-01", "Yesterday"), Ok(date("2024-02-29")));
+        assert_eq!(date_at("2024-03-01", "+3m"), Err("invalid date `+3m`".to_string()));
+        assert!(date_at("2024-03-01", "+99999999999999w").is_err());
+
+        let report = Item {
+            due: item::today().checked_add_days(Days::new(2)),
+            ..item("Write report")
+        };
+        assert!(matches("due<+3d", Status::Todo, &report));
+        assert!(!matches("due<tomorrow", Status::Todo, &report));
+        assert!(matches("is:overdue or due>=today", Status::Todo, &report));
+    }
+
+    #[test]
+    fn relative_dates_count_from_the_day_of_evaluation() {
+        let query = Query::parse("due:today or is:overdue").unwrap();
+        let report = Item {
+            due: Some(date("2024-03-01")),
+            ..item("Write report")
+        };
+        assert!(!query.matches(Status::Todo, &report, date("2024-02-29")));
+        assert!(query.matches(Status::Todo, &report, date("2024-03-01")));
+        assert!(query.matches(Status::Todo, &report, date("2024-03-02")));
+        assert!(!query.matches(Status::Done, &report, date("2024-03-02")));
+    }
+
+    #[test]
+    fn errors_point_at_the_column() {
+        let error = |query| Query::parse(query).unwrap_err();
+        assert_eq!(error("milk )"), "unexpected `)` at column 6");
+        assert_eq!(error("milk (eggs"), "unclosed `(` at column 6");
+        assert_eq!(error("tag:a due<soon"), "invalid date `soon` at column 7");
+        assert_eq!(error("a prio~A"), "`~` can't be used with `prio` at column 3");
+        assert_eq!(error("text:\"deploy"), "unclosed quote at column 6");
+        assert_eq!(error("milk or"), "unexpected end of query");
+    }
+}
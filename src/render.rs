@@ -0,0 +1,424 @@
+// Draws a frame of the terminal UI: the TODO and DONE lists side by side and
+// the status bar below them.
+
+use std::ops::Range;
+
+use crossterm::style::Color;
+
+use crate::backend::Backend;
+use crate::item::{self, Item};
+use crate::screen_buf::text_width;
+use crate::search::PromptKind;
+use crate::storage;
+use crate::theme::{Style, Theme};
+use crate::ui::{LayoutKind, Span, Ui, Vec2};
+use crate::{display_item, App, ItemList, Status};
+
+// Below this the lists aren't drawn, a header, one row and the status bar
+// need to fit
+pub const MIN_WIDTH: usize = 24;
+pub const MIN_HEIGHT: usize = 3;
+
+// Lays out and draws `app` on the whole screen, at whatever size it has now
+pub fn frame(app: &mut App, ui: &mut Ui<impl Backend>, theme: &Theme) {
+    let (w, h) = ui.size();
+    // One row for the column headers and one for the status bar
+    app.viewport_height = h.saturating_sub(2);
+    app.scroll_to_cursor();
+    let height = app.viewport_height;
+
+    if w < MIN_WIDTH || h < MIN_HEIGHT {
+        too_small(ui, theme, w);
+        return;
+    }
+    // A column between the lists keeps long TODO titles off the DONE ones
+    let todo_width = (w / 2) as i32 - 1;
+    let done_width = (w - w / 2) as i32;
+
+    ui.begin(Vec2::null(), LayoutKind::Vert);
+    {
+        ui.begin_layout(LayoutKind::Horz);
+        {
+            ui.begin_layout(LayoutKind::Vert);
+            {
+                let list = &app.lists[Status::Todo as usize];
+                let rows = app.rows(Status::Todo);
+                let header = list_header("TODO", list, height, &rows);
+                ui.label_fixed_width(&header, todo_width, theme.header);
+                let cursor_style = match app.active_status {
+                    Status::Todo => theme.selected,
+                    Status::Done => theme.selected_inactive,
+                };
+                for (index, todo) in list.visible(height, &rows) {
+                    let selected = index == list.cursor && !app.edit_mode;
+                    let cursor = selected.then_some(cursor_style);
+                    let editing = app.edit_mode && index == app.active_cursor();
+                    // While editing only the title is shown, that is what the editor holds
+                    if editing {
+                        let text = format!("[ ] {}", todo.title);
+                        let caret = 4 + app.editor.caret_column();
+                        ui.label_edit(&text, todo_width, theme.normal, caret, theme.edit_caret);
+                    } else {
+                        let text = format!("[ ] {}", display_item(todo));
+                        let (style, spans) =
+                            row_style(app, theme, Status::Todo, todo, &text, cursor);
+                        ui.label_highlight(&text, todo_width, style, &spans);
+                    }
+                }
+            }
+            ui.end_layout();
+            ui.space(1);
+            ui.begin_layout(LayoutKind::Vert);
+            {
+                let list = &app.lists[Status::Done as usize];
+                let rows = app.rows(Status::Done);
+                let header = list_header("DONE", list, height, &rows);
+                ui.label_fixed_width(&header, done_width, theme.header);
+                let cursor_style = match app.active_status {
+                    Status::Done => theme.selected,
+                    Status::Todo => theme.selected_inactive,
+                };
+                for (index, todo) in list.visible(height, &rows) {
+                    let selected = index == list.cursor && !app.edit_mode;
+                    let cursor = selected.then_some(cursor_style);
+                    let text = format!("[x] {}", display_item(todo));
+                    let (style, spans) = row_style(app, theme, Status::Done, todo, &text, cursor);
+                    ui.label_highlight(&text, done_width, style, &spans);
+                }
+            }
+            ui.end_layout();
+
+        }
+        ui.end_layout();
+    }
+
+    let edit_state = if app.edit_mode { "Edit" } else { "View" };
+    let mut prompt = format!("{}: {:?}", edit_state, app.active_status);
+    let pending = match &app.vim {
+        Some(vim) if !vim.pending().is_empty() => vim.pending(),
+        _ => app.keymap.pending(),
+    };
+    if !pending.is_empty() {
+        prompt.push_str(&format!("  {}", pending));
+    }
+    if app.changed_at.is_some() {
+        prompt.push_str(" [+]");
+    }
+    if let Some(filter) = &app.filter {
+        match app.view_index() {
+            Some(index) => prompt.push_str(&format!("  View: {}", app.views[index].name)),
+            None => prompt.push_str(&format!("  Filter: {}", filter.text)),
+        }
+    }
+    let style = match (app.status_error(), &app.message) {
+        (Some(error), _) => {
+            prompt = format!("{}  {}", prompt, error);
+            theme.status_error
+        }
+        (None, Some(message)) => {
+            prompt = format!("{}  {}", prompt, message);
+            theme.status_bar
+        }
+        (None, None) => theme.status_bar,
+    };
+    // The search or filter being typed replaces the status bar
+    let mut caret = None;
+    if let Some(input) = &app.prompt {
+        let label = match input.kind {
+            PromptKind::Search => "/",
+            PromptKind::Filter => "Filter: ",
+        };
+        prompt = format!("{}{}", label, input.text);
+        caret = Some(text_width(&prompt));
+        match input.kind {
+            PromptKind::Search => {
+                prompt.push_str(&format!("   [{}, Tab to change]", input.mode.name()))
+            }
+            PromptKind::Filter => prompt.push_str("   [e.g. status:todo tag:home due<+7d]"),
+        }
+        if let Some(error) = &input.error {
+            prompt.push_str(&format!("  {}", error));
+        }
+    }
+    let prompt = format!("{:width$}", prompt, width = w);
+    let y = h.saturating_sub(1);
+    ui.screen.put_cells(0, y, &prompt, style.fg, style.bg, style.attrs);
+    if let Some(x) = caret {
+        ui.screen.put_cell(x, y, ' ', style.bg, style.fg, style.attrs);
+    }
+
+    ui.end();
+}
+
+// Says so instead of the lists, in as much of the message as fits
+fn too_small(ui: &mut Ui<impl Backend>, theme: &Theme, w: usize) {
+    let message = format!("Terminal too small, {}x{} needed", MIN_WIDTH, MIN_HEIGHT);
+    ui.begin(Vec2::null(), LayoutKind::Vert);
+    ui.label_fixed_width(&message, w as i32, theme.status_error);
+    ui.end();
+}
+
+// Column header with the number of shown items if some are filtered out and
+// markers for items scrolled out of view, e.g. "TODO 15/40 ▲ 3 ▼ 12"
+fn list_header(title: &str, list: &ItemList, height: usize, rows: &[usize]) -> String {
+    let mut header = title.to_string();
+    if rows.len() != list.items.len() {
+        header.push_str(&format!(" {}/{}", rows.len(), list.items.len()));
+    }
+    if list.has_more_above() {
+        header.push_str(&format!(" ▲ {}", list.scroll));
+    }
+    if list.has_more_below(height, rows) {
+        let below = rows.len() - list.scroll - height;
+        header.push_str(&format!(" ▼ {}", below));
+    }
+    header
+}
+
+// Byte ranges of the search matches in a row showing `text`, without its
+// `prefix` bytes (the checkbox)
+fn search_matches(app: &App, text: &str, prefix: usize) -> Vec<Range<usize>> {
+    match &app.search {
+        Some(search) => search
+            .find(&text[prefix..])
+            .into_iter()
+            .map(|range| range.start + prefix..range.end + prefix)
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+// Byte ranges of the words in `text` naming one of the item's tags
+fn tag_words(item: &Item, text: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for word in text.split(' ') {
+        let tagged = item.tags.iter().any(|tag| {
+            word.strip_prefix('#') == Some(tag.as_str()) || word == storage::todotxt::tag_token(tag)
+        });
+        if tagged {
+            ranges.push(start..start + word.len());
+        }
+        start += word.len() + 1;
+    }
+    ranges
+}
+
+// Style of a row of the `status` list showing `text` (checkbox included) and
+// where the tags and search matches in it go. The cursor only recolours the
+// background of tags and overdue items so they stay recognizable, tags and
+// matches keep the row's attributes so a DONE item is struck through from end
+// to end.
+fn row_style(
+    app: &App,
+    theme: &Theme,
+    status: Status,
+    item: &Item,
+    text: &str,
+    cursor: Option<Style>,
+) -> (Style, Vec<Span>) {
+    // DONE items loaded from files without completion dates have none
+    let mut style = if status == Status::Done {
+        theme.done
+    } else if item.is_overdue(item::today()) {
+        theme.overdue
+    } else {
+        theme.normal
+    };
+    if let Some(cursor) = cursor {
+        style = if style == theme.normal {
+            cursor
+        } else {
+            Style { bg: cursor.bg, ..style }
+        };
+    }
+    let tag = Style {
+        bg: cursor.map_or(theme.tag.bg, |cursor| cursor.bg),
+        attrs: theme.tag.attrs | style.attrs,
+        ..theme.tag
+    };
+    let mut spans: Vec<_> = tag_words(item, text)
+        .into_iter()
+        .map(|range| (range, tag))
+        .collect();
+    let highlight = Style { attrs: theme.search_match.attrs | style.attrs, ..theme.search_match };
+    spans.extend(search_matches(app, text, 4).into_iter().map(|range| (range, highlight)));
+    (style, spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::backend::Headless;
+    use crate::color::Palette;
+    use crate::screen_buf::VirtualScreen;
+    use crate::search::{MatchMode, Pattern};
+
+    const WIDTH: usize = 48;
+    const HEIGHT: usize = 7;
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, item::DATE_FORMAT).unwrap()
+    }
+
+    fn app() -> App {
+        let mut milk = Item::from_title("Buy milk");
+        milk.priority = Some('A');
+        milk.tags = vec!["home".to_string()];
+        let mut report = Item::from_title("Write report");
+        report.due = Some(date("2001-02-03"));
+        let mut plumber = Item::from_title("Call the plumber");
+        plumber.notes = "after 5pm".to_string();
+        let rent = Item::from_title("Pay rent");
+
+        let mut app = App::new();
+        app.lists[Status::Todo as usize].items = vec![milk, report, plumber];
+        app.lists[Status::Done as usize].items = vec![rent];
+        app.assign_ids();
+        app
+    }
+
+    fn ui(palette: Palette) -> Ui<Headless> {
+        let theme = Theme::dark();
+        let mut screen = VirtualScreen::new(WIDTH, HEIGHT);
+        screen.set_palette(palette);
+        screen.set_blank(theme.normal.fg, theme.normal.bg);
+        Ui::new(screen, Headless::default())
+    }
+
+    fn render(app: &mut App, palette: Palette) -> String {
+        let mut ui = ui(palette);
+        frame(app, &mut ui, &Theme::dark());
+        ui.backend().snapshot()
+    }
+
+    #[test]
+    fn lists() {
+        insta::assert_snapshot!(render(&mut app(), Palette::TrueColor));
+    }
+
+    #[test]
+    fn cursor_in_done_list() {
+        let mut app = app();
+        app.active_status = Status::Done;
+        insta::assert_snapshot!(render(&mut app, Palette::TrueColor));
+    }
+
+    #[test]
+    fn search_matches_highlighted() {
+        let mut app = app();
+        app.search = Some(Pattern::new("r", MatchMode::IgnoreCase).unwrap());
+        insta::assert_snapshot!(render(&mut app, Palette::TrueColor));
+    }
+
+    #[test]
+    fn edit_mode() {
+        let mut app = app();
+        app.cursor_down();
+        app.set_edit(true);
+        app.edit(|editor| editor.word_left());
+        insta::assert_snapshot!(render(&mut app, Palette::TrueColor));
+    }
+
+    #[test]
+    fn scrolled_list() {
+        let mut app = app();
+        let items = &mut app.lists[Status::Todo as usize].items;
+        for number in 1..=6 {
+            items.push(Item::from_title(&format!("Item {}", number)));
+        }
+        app.assign_ids();
+        app.cursor_down_by(5);
+        insta::assert_snapshot!(render(&mut app, Palette::TrueColor));
+    }
+
+    #[test]
+    fn save_error() {
+        let mut app = app();
+        app.save_error = Some("ERROR: saving failed: disk full".to_string());
+        insta::assert_snapshot!(render(&mut app, Palette::TrueColor));
+    }
+
+    #[test]
+    fn monochrome() {
+        let mut app = app();
+        app.search = Some(Pattern::new("plumber", MatchMode::IgnoreCase).unwrap());
+        insta::assert_snapshot!(render(&mut app, Palette::Mono));
+    }
+
+    #[test]
+    fn long_titles_are_cut_at_the_column() {
+        let mut app = app();
+        let rent = &mut app.lists[Status::Done as usize].items[0];
+        rent.title = "Pay rent for March and April".to_string();
+        app.search = Some(Pattern::new("plumber", MatchMode::IgnoreCase).unwrap());
+        app.active_status = Status::Done;
+        let mut ui = ui(Palette::TrueColor);
+        ui.resize(40, HEIGHT);
+        frame(&mut app, &mut ui, &Theme::dark());
+        insta::assert_snapshot!(ui.backend().snapshot());
+    }
+
+    #[test]
+    fn editing_scrolls_to_the_caret() {
+        let mut app = app();
+        app.cursor_down_by(2);
+        app.set_edit(true);
+        for c in " before the weekend".chars() {
+            app.edit_add_char(c);
+        }
+        insta::assert_snapshot!(render(&mut app, Palette::TrueColor));
+    }
+
+    #[test]
+    fn resize_repaints_at_the_new_size() {
+        let mut app = app();
+        let mut ui = ui(Palette::TrueColor);
+        frame(&mut app, &mut ui, &Theme::dark());
+        ui.resize(30, 4);
+        frame(&mut app, &mut ui, &Theme::dark());
+        insta::assert_snapshot!(ui.backend().snapshot());
+    }
+
+    #[test]
+    fn terminal_too_small() {
+        let mut app = app();
+        let mut ui = ui(Palette::TrueColor);
+        ui.resize(MIN_WIDTH - 1, 4);
+        frame(&mut app, &mut ui, &Theme::dark());
+        insta::assert_snapshot!(ui.backend().snapshot());
+    }
+
+    #[test]
+    fn wide_and_combining_characters() {
+        let mut app = app();
+        let todo = &mut app.lists[Status::Todo as usize].items;
+        todo[0].title = "Milch kaufen, Straße fegen".to_string();
+        todo[1].title = "報告書を書く 📝".to_string();
+        todo[2].title = "Herrn Mu\u{308}ller anrufen 👨‍👩‍👧".to_string();
+        app.lists[Status::Done as usize].items[0].title = "家賃を払う🎉🎉🎉".to_string();
+        app.search = Some(Pattern::new("書", MatchMode::IgnoreCase).unwrap());
+        insta::assert_snapshot!(render(&mut app, Palette::TrueColor));
+    }
+
+    #[test]
+    fn editing_wide_text() {
+        let mut app = app();
+        app.set_edit(true);
+        for c in "は牛乳と卵も忘れずに".chars() {
+            app.edit_add_char(c);
+        }
+        // on 忘
+        for _ in 0..4 {
+            app.edit(|editor| editor.left());
+        }
+        insta::assert_snapshot!(render(&mut app, Palette::TrueColor));
+    }
+
+    #[test]
+    fn empty_lists() {
+        insta::assert_snapshot!(render(&mut App::new(), Palette::TrueColor));
+    }
+}
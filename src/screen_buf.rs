@@ -5,10 +5,16 @@ use std::{
 
 use crossterm::{
     cursor::MoveTo,
-    style::{Color, Print, SetBackgroundColor, SetForegroundColor},
+    style::{Attribute, Color, Print, SetAttribute, SetBackgroundColor, SetForegroundColor},
     terminal::{Clear, ClearType},
 };
 use crossterm::{execute, queue, ExecutableCommand, QueueableCommand};
+use bitflags::bitflags;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::backend::Backend;
+use crate::color::Palette;
 
 #[derive(Default)]
 pub struct VirtualScreen {
@@ -24,8 +30,9 @@ impl VirtualScreen {
         }
     }
 
-    pub fn flush(&self, qc: &mut impl Write) -> io::Result<()> {
-        self.buf_prev.flush(qc)
+    // Draws the last frame again in full
+    pub fn repaint(&self, backend: &mut impl Backend) -> io::Result<()> {
+        backend.repaint(&self.buf_prev)
     }
 
     pub fn resize(&mut self, width: usize, height: usize) {
@@ -33,45 +40,129 @@ impl VirtualScreen {
         self.buf_prev.resize(width, height);
     }
 
+    pub fn size(&self) -> (usize, usize) {
+        (self.buf_curr.width, self.buf_curr.height)
+    }
+
+    // Colours of the cells nothing is drawn on, clears the screen
+    pub fn set_blank(&mut self, fg: Color, bg: Color) {
+        self.buf_curr.set_blank(fg, bg);
+        self.buf_prev.set_blank(fg, bg);
+    }
+
+    // Colours the terminal can show, clears the screen
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.buf_curr.set_palette(palette);
+        self.buf_prev.set_palette(palette);
+    }
+
     pub fn diff(&self) -> Vec<Patch> {
         self.buf_prev.diff(&self.buf_curr)
     }
 
-    pub fn put_cell(&mut self, x: usize, y: usize, ch: char, fg: Color, bg: Color) {
-        self.buf_curr.put_cell(x, y, ch, fg, bg)
+    pub fn put_cell(&mut self, x: usize, y: usize, ch: char, fg: Color, bg: Color, attrs: Attrs) {
+        self.buf_curr.put_cell(x, y, ch, fg, bg, attrs)
     }
 
-    pub fn put_cells(&mut self, x: usize, y: usize, chs: &str, fg: Color, bg: Color) {
-        self.buf_curr.put_cells(x, y, chs, fg, bg)
+    pub fn put_cells(&mut self, x: usize, y: usize, chs: &str, fg: Color, bg: Color, attrs: Attrs) {
+        self.buf_curr.put_cells(x, y, chs, fg, bg, attrs)
     }
 
     pub fn swap(&mut self) {
         mem::swap(&mut self.buf_curr, &mut self.buf_prev);
+        // Start every frame from a blank buffer, otherwise rows that are no
+        // longer drawn (e.g. after scrolling) keep content from two frames ago.
+        self.buf_curr.clear();
     }
 }
 
+// Columns a grapheme takes on screen: 2 for East Asian wide and fullwidth
+// characters and most emoji, 0 for ones that draw nothing on their own
+pub fn grapheme_width(grapheme: &str) -> usize {
+    grapheme.width().min(2)
+}
+
+pub fn text_width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+// The longest start of `text` that fits in `width` columns, in whole graphemes
+pub fn fit(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (at, grapheme) in text.grapheme_indices(true) {
+        used += grapheme_width(grapheme);
+        if used > width {
+            return &text[..at];
+        }
+    }
+    text
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct Attrs: u8 {
+        const BOLD = 1;
+        const ITALIC = 1 << 1;
+        const UNDERLINE = 1 << 2;
+        const STRIKETHROUGH = 1 << 3;
+        const DIM = 1 << 4;
+        const REVERSE = 1 << 5;
+    }
+}
+
+// The SGR attributes turning each flag on and off
+const ATTRIBUTES: [(Attrs, Attribute, Attribute); 6] = [
+    (Attrs::BOLD, Attribute::Bold, Attribute::NormalIntensity),
+    (Attrs::DIM, Attribute::Dim, Attribute::NormalIntensity),
+    (Attrs::ITALIC, Attribute::Italic, Attribute::NoItalic),
+    (Attrs::UNDERLINE, Attribute::Underlined, Attribute::NoUnderline),
+    (Attrs::STRIKETHROUGH, Attribute::CrossedOut, Attribute::NotCrossedOut),
+    (Attrs::REVERSE, Attribute::Reverse, Attribute::NoReverse),
+];
+
 #[derive(Debug, Clone, PartialEq)]
 struct Cell {
-    ch: char,
+    // a grapheme, empty in the right half of a wide one
+    symbol: String,
     fg: Color,
     bg: Color,
+    // with the stand-ins for colours without a palette, see Buffer::cell()
+    attrs: Attrs,
 }
 
 impl Default for Cell {
     fn default() -> Self {
         Self {
-            ch: ' ',
+            symbol: " ".to_string(),
             fg: Color::White,
             bg: Color::Black,
+            attrs: Attrs::empty(),
         }
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct Buffer {
     cells: Vec<Cell>,
     width: usize,
     height: usize,
+    palette: Palette,
+    // colours of the cells nothing is drawn on, as given to set_blank()
+    blank_colors: (Color, Color),
+    // what clear() and resize() fill the buffer with
+    blank: Cell,
+}
+
+impl Cell {
+    fn is_continuation(&self) -> bool {
+        self.symbol.is_empty()
+    }
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self::new(0, 0)
+    }
 }
 
 pub struct Patch {
@@ -83,20 +174,56 @@ pub struct Patch {
 impl Buffer {
     pub fn new(width: usize, height: usize) -> Self {
         let cells = vec![Cell::default(); width * height];
+        let blank = Cell::default();
         Self {
             cells,
             width,
             height,
+            palette: Palette::default(),
+            blank_colors: (blank.fg, blank.bg),
+            blank,
         }
     }
 
     pub fn resize(&mut self, width: usize, height: usize) {
-        self.cells.resize(width * height, Cell::default());
-        self.cells.fill(Cell::default());
+        self.cells.resize(width * height, self.blank.clone());
+        self.cells.fill(self.blank.clone());
         self.width = width;
         self.height = height;
     }
 
+    pub fn set_blank(&mut self, fg: Color, bg: Color) {
+        self.blank_colors = (fg, bg);
+        self.blank = self.cell(" ", fg, bg, Attrs::empty());
+        self.clear();
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        let (fg, bg) = self.blank_colors;
+        self.set_blank(fg, bg);
+    }
+
+    // `symbol` in colours the palette has. Without colours a background other
+    // than the blank one (selection, status bar, search matches) becomes
+    // reverse video and any other foreground bold.
+    fn cell(&self, symbol: &str, fg: Color, bg: Color, attrs: Attrs) -> Cell {
+        let mut attrs = attrs;
+        if self.palette == Palette::Mono {
+            if bg != self.blank_colors.1 {
+                attrs |= Attrs::REVERSE;
+            } else if fg != self.blank_colors.0 {
+                attrs |= Attrs::BOLD;
+            }
+        }
+        Cell {
+            symbol: symbol.to_string(),
+            fg: self.palette.map(fg),
+            bg: self.palette.map(bg),
+            attrs,
+        }
+    }
+
     pub fn diff(&self, other: &Self) -> Vec<Patch> {
         assert!(self.width == other.width && self.height == other.height);
         self.cells
@@ -117,48 +244,127 @@ impl Buffer {
     }
 
     pub fn clear(&mut self) {
-        self.cells.fill(Cell::default());
+        self.cells.fill(self.blank.clone());
+    }
+
+    pub fn put_cell(&mut self, x: usize, y: usize, ch: char, fg: Color, bg: Color, attrs: Attrs) {
+        self.put_cells(x, y, ch.encode_utf8(&mut [0; 4]), fg, bg, attrs)
     }
 
-    pub fn put_cell(&mut self, x: usize, y: usize, ch: char, fg: Color, bg: Color) {
-        if let Some(cell) = self.cells.get_mut(y * self.width + x) {
-            *cell = Cell { ch, fg, bg }
+    // Text running past the end of the row is cut off, not wrapped to the next
+    // one. A wide grapheme that would only half fit leaves a space instead.
+    pub fn put_cells(
+        &mut self,
+        x: usize,
+        y: usize,
+        text: &str,
+        fg: Color,
+        bg: Color,
+        attrs: Attrs,
+    ) {
+        if y >= self.height {
+            return;
+        }
+        let mut x = x;
+        for grapheme in text.graphemes(true) {
+            let width = grapheme_width(grapheme);
+            if width == 0 {
+                continue;
+            }
+            if x + width > self.width {
+                if x < self.width {
+                    self.put(x, y, 1, self.cell(" ", fg, bg, attrs));
+                }
+                break;
+            }
+            self.put(x, y, width, self.cell(grapheme, fg, bg, attrs));
+            x += width;
         }
     }
 
-    pub fn put_cells(&mut self, x: usize, y: usize, chs: &str, fg: Color, bg: Color) {
+    // Puts `cell` `width` columns wide at `x`, `y`. Wide graphemes it lands
+    // on half of lose their other half to a space.
+    fn put(&mut self, x: usize, y: usize, width: usize, cell: Cell) {
         let start = y * self.width + x;
-        for (offset, ch) in chs.chars().enumerate() {
-            if let Some(cell) = self.cells.get_mut(start + offset) {
-                *cell = Cell { ch, fg, bg };
-            } else {
-                break;
+        let end = start + width;
+        if x > 0 && self.cells[start].is_continuation() {
+            self.cells[start - 1].symbol = " ".to_string();
+        }
+        if x + width < self.width && self.cells[end].is_continuation() {
+            self.cells[end].symbol = " ".to_string();
+        }
+        if width == 2 {
+            self.cells[start + 1] = Cell { symbol: String::new(), ..cell.clone() };
+        }
+        self.cells[start] = cell;
+    }
+
+    #[cfg(test)]
+    pub fn apply(&mut self, patch: &Patch) {
+        if let Some(cell) = self.cells.get_mut(patch.y * self.width + patch.x) {
+            *cell = patch.cell.clone();
+        }
+    }
+
+    // The rows between bars, then a letter per cell for its style and what
+    // the letters stand for:
+    //
+    //     |TODO  DONE  |
+    //     |aaaaaaaaaaaa|
+    //     a: Cyan on Black bold
+    #[cfg(test)]
+    pub fn snapshot(&self) -> String {
+        const LETTERS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+        let mut styles: Vec<(Color, Color, Attrs)> = Vec::new();
+        let mut text = String::new();
+        let mut letters = String::new();
+        for row in self.cells.chunks(self.width.max(1)) {
+            text.push('|');
+            letters.push('|');
+            for cell in row {
+                let style = (cell.fg, cell.bg, cell.attrs);
+                let index = match styles.iter().position(|known| *known == style) {
+                    Some(index) => index,
+                    None => {
+                        styles.push(style);
+                        styles.len() - 1
+                    }
+                };
+                text.push_str(&cell.symbol);
+                letters.push(LETTERS.chars().nth(index).unwrap_or('?'));
             }
+            text.push_str("|\n");
+            letters.push_str("|\n");
         }
+        let mut snapshot = format!("{}\n{}\n", text, letters);
+        for (letter, (fg, bg, attrs)) in LETTERS.chars().zip(styles) {
+            snapshot.push_str(&format!("{}: {:?} on {:?}", letter, fg, bg));
+            for (name, _) in attrs.iter_names() {
+                snapshot.push_str(&format!(" {}", name.to_lowercase()));
+            }
+            snapshot.push('\n');
+        }
+        snapshot
     }
 
     pub fn flush(&self, qc: &mut impl Write) -> io::Result<()> {
-        let mut fg_curr = Color::White;
-        let mut bg_curr = Color::Black;
+        let mut pen = Pen::default();
 
         queue!(
             qc,
+            SetAttribute(Attribute::Reset),
             Clear(ClearType::All),
-            SetForegroundColor(fg_curr),
-            SetBackgroundColor(bg_curr),
             MoveTo(0, 0),
         )?;
 
-        for Cell { ch, fg, bg } in self.cells.iter() {
-            if fg_curr != *fg {
-                fg_curr = *fg;
-                qc.queue(SetForegroundColor(fg_curr))?;
-            }
-            if bg_curr != *bg {
-                bg_curr = *bg;
-                qc.queue(SetBackgroundColor(bg_curr))?;
+        // Every row starts with a move, so a terminal that draws a grapheme
+        // wider or narrower than we think only misaligns the rest of its row
+        for (y, row) in self.cells.chunks(self.width.max(1)).enumerate() {
+            qc.queue(MoveTo(0, y as u16))?;
+            for cell in row.iter().filter(|cell| !cell.is_continuation()) {
+                pen.set(qc, cell)?;
+                qc.queue(Print(&cell.symbol))?;
             }
-            qc.queue(Print(ch))?;
         }
 
         qc.flush()?;
@@ -166,34 +372,164 @@ impl Buffer {
     }
 }
 
+// Style the terminal draws with, starts out as after an attribute reset so
+// nothing about colours is written when all cells use the default ones
+struct Pen {
+    fg: Color,
+    bg: Color,
+    attrs: Attrs,
+}
 
-pub fn apply_patches(qc: &mut impl QueueableCommand, patches: &[Patch]) -> io::Result<()> {
-    let mut fg_curr = Color::White;
-    let mut bg_curr = Color::Black;
-    let mut x_prev = 0;
-    let mut y_prev = 0;
-    qc.queue(SetForegroundColor(fg_curr))?;
-    qc.queue(SetBackgroundColor(bg_curr))?;
-    for Patch {
-        cell: Cell { ch, fg, bg },
-        x,
-        y,
-    } in patches
-    {
-        if !(y_prev == *y && x_prev + 1 == *x) {
-            qc.queue(MoveTo(*x as u16, *y as u16))?;
+impl Default for Pen {
+    fn default() -> Self {
+        Self {
+            fg: Color::Reset,
+            bg: Color::Reset,
+            attrs: Attrs::empty(),
         }
-        x_prev = *x;
-        y_prev = *y;
-        if fg_curr != *fg {
-            fg_curr = *fg;
-            qc.queue(SetForegroundColor(fg_curr))?;
+    }
+}
+
+impl Pen {
+    // Queues the changes needed to draw `cell`
+    fn set(&mut self, qc: &mut impl QueueableCommand, cell: &Cell) -> io::Result<()> {
+        if self.fg != cell.fg {
+            self.fg = cell.fg;
+            qc.queue(SetForegroundColor(self.fg))?;
         }
-        if bg_curr != *bg {
-            bg_curr = *bg;
-            qc.queue(SetBackgroundColor(bg_curr))?;
+        if self.bg != cell.bg {
+            self.bg = cell.bg;
+            qc.queue(SetBackgroundColor(self.bg))?;
+        }
+        let intensity = Attrs::BOLD | Attrs::DIM;
+        let mut removed = self.attrs - cell.attrs;
+        let mut added = cell.attrs - self.attrs;
+        // NormalIntensity ends both bold and dim, the one that stays is set again
+        if removed.intersects(intensity) {
+            qc.queue(SetAttribute(Attribute::NormalIntensity))?;
+            removed -= intensity;
+            added |= cell.attrs & intensity;
+        }
+        for (flag, on, off) in ATTRIBUTES {
+            if removed.contains(flag) {
+                qc.queue(SetAttribute(off))?;
+            } else if added.contains(flag) {
+                qc.queue(SetAttribute(on))?;
+            }
         }
-        qc.queue(Print(ch))?;
+        self.attrs = cell.attrs;
+        Ok(())
+    }
+}
+
+
+pub fn apply_patches(qc: &mut impl QueueableCommand, patches: &[Patch]) -> io::Result<()> {
+    let mut pen = Pen::default();
+    // where the terminal's cursor is after the last print
+    let mut cursor = None;
+    if patches.is_empty() {
+        return Ok(());
+    }
+    qc.queue(SetAttribute(Attribute::Reset))?;
+    for Patch { cell, x, y } in patches {
+        // drawn by the wide grapheme to its left, which changed with it
+        if cell.is_continuation() {
+            continue;
+        }
+        if cursor != Some((*x, *y)) {
+            qc.queue(MoveTo(*x as u16, *y as u16))?;
+        }
+        pen.set(qc, cell)?;
+        qc.queue(Print(&cell.symbol))?;
+        cursor = Some((x + grapheme_width(&cell.symbol), *y));
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> Buffer {
+        let mut buffer = Buffer::new(6, 1);
+        buffer.put_cells(0, 0, text, Color::White, Color::Black, Attrs::empty());
+        buffer
+    }
+
+    fn row(buffer: &Buffer) -> String {
+        buffer.snapshot().lines().next().unwrap_or_default().to_string()
+    }
+
+    // What apply_patches() writes to turn `prev` into `curr`
+    fn patch(prev: &Buffer, curr: &Buffer) -> String {
+        let mut out = Vec::new();
+        apply_patches(&mut out, &prev.diff(curr)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn wide_graphemes_take_two_cells() {
+        let mut wide = buffer("日本語");
+        assert_eq!(row(&wide), "|日本語|");
+        // on the right half of 日
+        wide.put_cells(1, 0, "x", Color::White, Color::Black, Attrs::empty());
+        assert_eq!(row(&wide), "| x本語|");
+        // 語 only half fits
+        wide.put_cells(5, 0, "語", Color::White, Color::Black, Attrs::empty());
+        assert_eq!(row(&wide), "| x本  |");
+        assert_eq!(row(&buffer("ab語cd")), "|ab語cd|");
+        assert_eq!(row(&buffer("abcde語")), "|abcde |");
+    }
+
+    #[test]
+    fn grapheme_clusters_share_a_cell() {
+        assert_eq!(row(&buffer("Mu\u{308}ller")), "|Mu\u{308}ller|");
+        assert_eq!(row(&buffer("👨‍👩‍👧ok")), "|👨‍👩‍👧ok  |");
+        assert_eq!(text_width("Mu\u{308}ller 👨‍👩‍👧 日本"), 14);
+        assert_eq!(fit("日本語", 5), "日本");
+    }
+
+    #[test]
+    fn cursor_moves_past_wide_graphemes() {
+        // MoveTo(x, y) is ESC [ y+1 ; x+1 H, the colours follow it
+        let moves = |written: &str| written.matches('H').count();
+        let written = patch(&buffer(""), &buffer("日本x"));
+        assert!(written.contains("\x1b[1;1H") && written.ends_with("日本x"), "{:?}", written);
+        assert_eq!(moves(&written), 1, "{:?}", written);
+
+        let written = patch(&buffer("ab語cd"), &buffer("ab語xy"));
+        assert!(written.contains("\x1b[1;5H") && written.ends_with("xy"), "{:?}", written);
+        assert_eq!(moves(&written), 1, "{:?}", written);
+
+        let written = patch(&buffer("日本語"), &buffer("日a本"));
+        assert!(written.contains("\x1b[1;3H") && written.ends_with("a本 "), "{:?}", written);
+        assert_eq!(moves(&written), 1, "{:?}", written);
+    }
+
+    #[test]
+    fn only_changed_attributes_are_written() {
+        let cell = |attrs: Attrs| Cell { attrs, ..Cell::default() };
+        let written = |cells: &[Cell]| {
+            let mut pen = Pen { fg: Color::White, bg: Color::Black, ..Pen::default() };
+            let mut out = Vec::new();
+            for cell in cells {
+                pen.set(&mut out, cell).unwrap();
+                out.push(b'|');
+            }
+            String::from_utf8(out).unwrap()
+        };
+        // SGR 1 bold, 2 dim, 4 underline, 9 strikethrough, 22 neither bold
+        // nor dim, 24 no underline
+        let cells = [
+            cell(Attrs::BOLD | Attrs::UNDERLINE),
+            cell(Attrs::BOLD | Attrs::UNDERLINE),
+            cell(Attrs::BOLD | Attrs::DIM),
+            cell(Attrs::DIM | Attrs::STRIKETHROUGH),
+            cell(Attrs::empty()),
+        ];
+        assert_eq!(
+            written(&cells),
+            "\x1b[1m\x1b[4m||\x1b[2m\x1b[24m|\x1b[22m\x1b[2m\x1b[9m|\x1b[22m\x1b[29m|"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+// Searching and filtering items by what the user types into the prompt at the
+// bottom of the screen
+//
+// All match modes are compiled to a regex, a plain text is escaped first, so
+// matches can be highlighted the same way in every mode.
+
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::query::Query;
+use crate::Status;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MatchMode {
+    Substring,
+    #[default]
+    IgnoreCase,
+    Regex,
+}
+
+impl MatchMode {
+    pub fn next(self) -> Self {
+        match self {
+            MatchMode::Substring => MatchMode::IgnoreCase,
+            MatchMode::IgnoreCase => MatchMode::Regex,
+            MatchMode::Regex => MatchMode::Substring,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MatchMode::Substring => "exact",
+            MatchMode::IgnoreCase => "ignore case",
+            MatchMode::Regex => "regex",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub text: String,
+    pub mode: MatchMode,
+    regex: Regex,
+}
+
+impl Pattern {
+    pub fn new(text: &str, mode: MatchMode) -> Result<Self, String> {
+        let source = match mode {
+            MatchMode::Substring | MatchMode::IgnoreCase => regex::escape(text),
+            MatchMode::Regex => text.to_string(),
+        };
+        let regex = RegexBuilder::new(&source)
+            .case_insensitive(mode == MatchMode::IgnoreCase)
+            .build()
+            .map_err(|err| match err {
+                regex::Error::Syntax(_) => "invalid regex".to_string(),
+                err => err.to_string(),
+            })?;
+        Ok(Self {
+            text: text.to_string(),
+            mode,
+            regex,
+        })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+
+    // Byte ranges of the matches in `text`, empty matches have nothing to highlight
+    pub fn find(&self, text: &str) -> Vec<Range<usize>> {
+        self.regex
+            .find_iter(text)
+            .map(|found| found.range())
+            .filter(|range| !range.is_empty())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PromptKind {
+    Search,
+    Filter,
+}
+
+// The search or filter text being typed, applied on every key press. A
+// filter is written in the query language, see query.rs.
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub text: String,
+    pub mode: MatchMode,
+    // why `text` can't be used, e.g. an invalid regex
+    pub error: Option<String>,
+    // the cursor and the search or filter when the prompt was opened,
+    // restored when it is cancelled
+    pub origin: (Status, usize),
+    pub previous: Option<Pattern>,
+    pub previous_filter: Option<Query>,
+}
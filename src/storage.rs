@@ -0,0 +1,326 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+
+use crate::item::Item;
+use crate::Status;
+
+pub mod json;
+pub mod markdown;
+pub mod native;
+pub mod todotxt;
+
+pub trait Storage {
+    // Items in file order, the caller assigns ids to items that have none.
+    // Lines that can't be parsed are reported in Loaded::errors and written
+    // back unchanged on save, unless forget_errors() is called.
+    fn load(&mut self) -> Result<Loaded>;
+
+    // The lines behind the last Loaded::errors were imported some other way
+    fn forget_errors(&mut self) {}
+
+    fn save(&mut self, lists: &[(Status, &[Item])]) -> Result<()>;
+
+    // Whether item ids survive saving and loading again. Formats that can't
+    // store them number their items anew on every load.
+    fn keeps_ids(&self) -> bool {
+        true
+    }
+
+    // Whether tags and the due date are words of the title, so that changing
+    // the title changes them too, see todotxt::retitle()
+    fn metadata_in_titles(&self) -> bool {
+        false
+    }
+
+    // Whether the backing file was changed by someone else since the last load or save
+    fn watch(&mut self) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+#[derive(Default)]
+pub struct Loaded {
+    pub items: Vec<(Status, Item)>,
+    pub errors: Vec<ParseError>,
+}
+
+impl From<Vec<(Status, Item)>> for Loaded {
+    fn from(items: Vec<(Status, Item)>) -> Self {
+        Self {
+            items,
+            errors: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub path: PathBuf,
+    // 1-based
+    pub line: usize,
+    pub reason: String,
+    // the offending line as it is in the file
+    pub text: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: ERROR: {}: {}",
+            self.path.display(),
+            self.line,
+            self.reason,
+            self.text.trim()
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseErrors(pub Vec<ParseError>);
+
+impl fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ill-formed line(s)", self.0.len())?;
+        for error in self.0.iter() {
+            write!(f, "\n    {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseErrors {}
+
+// What to do with lines that couldn't be parsed
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Recovery {
+    Abort,
+    // keep them out of the lists but write them back on save
+    Skip,
+    // turn each line into a plain TODO item
+    Import,
+}
+
+#[derive(PartialEq, Default, Debug, Clone, Copy)]
+pub enum Format {
+    // TODO:/DONE: lines with indented metadata
+    #[default]
+    Native,
+    TodoTxt,
+    Markdown,
+    Json,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "native" => Some(Format::Native),
+            "todotxt" | "todo.txt" => Some(Format::TodoTxt),
+            "markdown" | "md" => Some(Format::Markdown),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+
+    // Picks the format from the file extension and, where that is ambiguous,
+    // from the file content. Missing or empty files default to Native.
+    pub fn detect(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("md" | "markdown") => return Format::Markdown,
+            Some("json") => return Format::Json,
+            _ => {}
+        }
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|text| Self::sniff(&text))
+            .unwrap_or_default()
+    }
+
+    fn sniff(text: &str) -> Option<Self> {
+        let first = text.lines().find(|line| !line.trim().is_empty())?;
+        // Native files may start with lines that aren't items, e.g. after a
+        // failed parse that was kept
+        let mut unindented = text.lines().filter(|line| !line.starts_with(char::is_whitespace));
+        if unindented.any(|line| native::parse_item(line.trim_end()).is_some()) {
+            Some(Format::Native)
+        } else if first.starts_with('{') {
+            Some(Format::Json)
+        } else if first.starts_with('#') || text.lines().any(markdown::is_task) {
+            Some(Format::Markdown)
+        } else {
+            Some(Format::TodoTxt)
+        }
+    }
+}
+
+pub fn open(path: &str, format: Option<Format>, backups: usize) -> Box<dyn Storage> {
+    let mut file = StorageFile::new(path);
+    file.backups = backups;
+    let format = format.unwrap_or_else(|| Format::detect(&file.path));
+    with_format(file, format)
+}
+
+pub fn with_format(file: StorageFile, format: Format) -> Box<dyn Storage> {
+    match format {
+        Format::Native => Box::new(native::NativeStorage::new(file)),
+        Format::TodoTxt => Box::new(todotxt::TodoTxtStorage::new(file)),
+        Format::Markdown => Box::new(markdown::MarkdownStorage::new(file)),
+        Format::Json => Box::new(json::JsonStorage::new(file)),
+    }
+}
+
+// The file behind a storage, remembers its modification time for Storage::watch()
+pub struct StorageFile {
+    pub path: PathBuf,
+    // number of previous versions kept as `<path>.1` (newest) to `<path>.<backups>`
+    pub backups: usize,
+    // backups are taken once per session, not on every save
+    backed_up: bool,
+    modified: Option<SystemTime>,
+}
+
+impl StorageFile {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            backups: 0,
+            backed_up: false,
+            modified: None,
+        }
+    }
+
+    pub fn read(&mut self) -> Result<String> {
+        // A list that doesn't exist yet is empty, the first save creates it
+        let text = match fs::read_to_string(&self.path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            text => text.with_context(|| format!("could not read {}", self.path.display()))?,
+        };
+        self.modified = self.mtime();
+        Ok(text)
+    }
+
+    pub fn write(&mut self, text: &str) -> Result<()> {
+        let backups = if self.backed_up { 0 } else { self.backups };
+        write_atomic(&self.path, text, backups)?;
+        self.backed_up = true;
+        self.modified = self.mtime();
+        Ok(())
+    }
+
+    pub fn changed(&self) -> bool {
+        self.mtime() != self.modified
+    }
+
+    fn mtime(&self) -> Option<SystemTime> {
+        fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+}
+
+// Writes `text` to a temporary file next to `path` and renames it over `path`
+// once it is on disk, so a crash mid-save never truncates the file. The
+// previous version is kept as a backup first if `backups` > 0.
+pub fn write_atomic(path: &Path, text: &str, backups: usize) -> Result<()> {
+    // Replace the file a symlink points to, not the symlink itself
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let tmp_path = with_suffix(&target, "tmp");
+
+    let result = write_synced(&tmp_path, &target, text)
+        .and_then(|()| rotate_backups(&target, backups))
+        .and_then(|()| Ok(fs::rename(&tmp_path, &target)?))
+        .and_then(|()| sync_parent_dir(&target));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+// An empty directory of its own for the test `name`
+#[cfg(test)]
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("todo-rs-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("the scratch directory should be created");
+    dir
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn write_synced(path: &Path, original: &Path, text: &str) -> Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(text.as_bytes())?;
+    file.sync_all()?;
+    if let Ok(metadata) = fs::metadata(original) {
+        fs::set_permissions(path, metadata.permissions())?;
+    }
+    Ok(())
+}
+
+// Shifts `<path>.1` .. `<path>.<count - 1>` up by one and copies `path` to `<path>.1`
+fn rotate_backups(path: &Path, count: usize) -> Result<()> {
+    if count == 0 || !path.exists() {
+        return Ok(());
+    }
+    for index in (1..count).rev() {
+        let from = with_suffix(path, &index.to_string());
+        if from.exists() {
+            fs::rename(&from, with_suffix(path, &(index + 1).to_string()))?;
+        }
+    }
+    fs::copy(path, with_suffix(path, "1"))?;
+    Ok(())
+}
+
+// Makes the rename itself durable
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn native_files_are_detected_by_any_item_line() {
+        assert_eq!(Format::sniff("TODO: \n    id: 1\nTODO: b\n"), Some(Format::Native));
+        assert_eq!(Format::sniff("Notes\nDONE: a\n"), Some(Format::Native));
+        assert_eq!(Format::sniff("Buy milk\n  TODO: indented\n"), Some(Format::TodoTxt));
+    }
+
+    #[test]
+    fn missing_file_is_created_on_save() {
+        let dir = scratch_dir("missing-file");
+        for format in [Format::Native, Format::TodoTxt, Format::Markdown, Format::Json] {
+            let path = dir.join(format!("{:?}", format));
+            let mut storage = with_format(StorageFile::new(path.to_str().unwrap()), format);
+            assert!(storage.load().unwrap().items.is_empty());
+            assert!(!path.exists());
+
+            storage.save(&[(Status::Todo, &[Item::new("Buy milk")])]).unwrap();
+            let loaded = storage.load().unwrap();
+            assert_eq!(loaded.items[0].1.title, "Buy milk", "{:?}", format);
+        }
+    }
+}
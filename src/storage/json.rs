@@ -0,0 +1,107 @@
+// JSON documents
+//
+//     {
+//       "version": 1,
+//       "items": [
+//         { "status": "todo", "id": 1, "title": "Buy milk", "due": "2024-03-01", ... }
+//       ]
+//     }
+//
+// The schema is documented in docs/todo.schema.json, bump SCHEMA_VERSION and
+// the schema together.
+
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{Loaded, Storage, StorageFile};
+use crate::item::Item;
+use crate::Status;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct Document {
+    pub version: u32,
+    pub items: Vec<Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub status: Status,
+    #[serde(flatten)]
+    pub item: Item,
+}
+
+impl Document {
+    pub fn new(lists: &[(Status, &[Item])]) -> Self {
+        let items = lists
+            .iter()
+            .flat_map(|(status, items)| {
+                items.iter().map(|item| Entry {
+                    status: *status,
+                    item: item.clone(),
+                })
+            })
+            .collect();
+        Self {
+            version: SCHEMA_VERSION,
+            items,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let document: Document = serde_json::from_str(text)?;
+        if document.version != SCHEMA_VERSION {
+            bail!(
+                "unsupported schema version {}, expected {}",
+                document.version,
+                SCHEMA_VERSION
+            );
+        }
+        let mut ids = HashSet::new();
+        for entry in &document.items {
+            if entry.item.id != 0 && !ids.insert(entry.item.id) {
+                bail!("id {} is used by more than one item", entry.item.id);
+            }
+        }
+        Ok(document)
+    }
+}
+
+pub struct JsonStorage {
+    file: StorageFile,
+}
+
+impl JsonStorage {
+    pub fn new(file: StorageFile) -> Self {
+        Self { file }
+    }
+}
+
+impl Storage for JsonStorage {
+    fn load(&mut self) -> Result<Loaded> {
+        let text = self.file.read()?;
+        if text.is_empty() {
+            return Ok(Loaded::default());
+        }
+        let document = Document::parse(&text)?;
+        let items: Vec<_> = document
+            .items
+            .into_iter()
+            .map(|entry| (entry.status, entry.item))
+            .collect();
+        Ok(items.into())
+    }
+
+    fn save(&mut self, lists: &[(Status, &[Item])]) -> Result<()> {
+        let mut text = serde_json::to_string_pretty(&Document::new(lists))?;
+        text.push('\n');
+        self.file.write(&text)
+    }
+
+    fn watch(&mut self) -> Result<bool> {
+        Ok(self.file.changed())
+    }
+}
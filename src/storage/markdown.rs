@@ -0,0 +1,423 @@
+// GitHub-flavoured Markdown task lists
+//
+//     ## Backend
+//     Some prose that is kept as is.
+//     - [ ] (A) Rotate the API keys #security due:2024-03-01
+//       Indented lines below a task are its notes.
+//     - [x] Deploy the new schema
+//
+// The file is kept as a document of lines in which every task line refers to
+// an item by id. On save only the checkbox, the text and the notes of those
+// lines are rewritten, so prose and headings stay in place. Tasks pick up the
+// nearest heading above them as their section. A leading `(A)` is the
+// priority, `#tag`, `@context` and `due:` words at the end of the text are
+// tags and the due date. Creation and completion times and extensions are not
+// kept.
+//
+// Items moved within their list swap places with the other tasks of that
+// list. Deleted items lose their line, new items are added after the last task
+// of the file.
+
+use anyhow::Result;
+
+use super::{Loaded, Storage, StorageFile};
+use crate::item::{self, Item, ItemId};
+use crate::Status;
+
+pub struct MarkdownStorage {
+    file: StorageFile,
+    document: Document,
+}
+
+impl MarkdownStorage {
+    pub fn new(file: StorageFile) -> Self {
+        Self {
+            file,
+            document: Document::default(),
+        }
+    }
+}
+
+impl Storage for MarkdownStorage {
+    fn load(&mut self) -> Result<Loaded> {
+        let text = self.file.read()?;
+        // The document needs ids to refer to its tasks, the file is the only
+        // source of items so numbering them from 1 is fine
+        let mut next_id = 0;
+        let (document, items) = Document::parse(&text, || {
+            next_id += 1;
+            next_id
+        });
+        self.document = document;
+        Ok(items.into())
+    }
+
+    fn save(&mut self, lists: &[(Status, &[Item])]) -> Result<()> {
+        let text = self.document.render(lists);
+        self.file.write(&text)
+    }
+
+    fn keeps_ids(&self) -> bool {
+        false
+    }
+
+    fn watch(&mut self) -> Result<bool> {
+        Ok(self.file.changed())
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Line {
+    Text(String),
+    Task {
+        id: ItemId,
+        // indentation and list marker, e.g. "  - " or "1. "
+        prefix: String,
+        // the character used for checked boxes, `x` or `X`
+        checked: char,
+        // the list of the item when the line was read or last written
+        status: Status,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    lines: Vec<Line>,
+}
+
+impl Document {
+    // Parses `text` and returns the found tasks with ids from `alloc_id`
+    pub fn parse(
+        text: &str,
+        mut alloc_id: impl FnMut() -> ItemId,
+    ) -> (Self, Vec<(Status, Item)>) {
+        let mut lines = Vec::new();
+        let mut items: Vec<(Status, Item)> = Vec::new();
+        let mut section = None;
+        let mut in_code_block = false;
+        // the item whose notes the following indented lines are, and their indentation
+        let mut notes_of: Option<(usize, usize)> = None;
+
+        for line in text.lines() {
+            if let Some((index, indent)) = notes_of {
+                let note = line.get(indent..).filter(|_| line[..indent].trim().is_empty());
+                match note {
+                    Some(note) if !note.trim().is_empty() && !is_task(line) => {
+                        let item = &mut items[index].1;
+                        if !item.notes.is_empty() {
+                            item.notes.push('\n');
+                        }
+                        item.notes.push_str(note);
+                        continue;
+                    }
+                    _ => notes_of = None,
+                }
+            }
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_code_block = !in_code_block;
+            }
+            if in_code_block {
+                lines.push(Line::Text(line.to_string()));
+                continue;
+            }
+            if let Some(heading) = parse_heading(line) {
+                section = Some(heading.to_string());
+            }
+            match parse_task(line) {
+                Some((prefix, checked, text)) => {
+                    let mut item = parse_text(text.trim_end());
+                    item.id = alloc_id();
+                    item.section = section.clone();
+                    let status = if checked.is_some() {
+                        Status::Done
+                    } else {
+                        Status::Todo
+                    };
+                    lines.push(Line::Task {
+                        id: item.id,
+                        prefix: prefix.to_string(),
+                        checked: checked.unwrap_or('x'),
+                        status,
+                    });
+                    notes_of = Some((items.len(), prefix.len()));
+                    items.push((status, item));
+                }
+                None => lines.push(Line::Text(line.to_string())),
+            }
+        }
+
+        (Self { lines }, items)
+    }
+
+    // Renders the document with the current state of `lists` and remembers
+    // the lines of newly added items for the next save.
+    pub fn render(&mut self, lists: &[(Status, &[Item])]) -> String {
+        let find = |id: ItemId| {
+            lists.iter().find_map(|(status, items)| {
+                items
+                    .iter()
+                    .find(|item| item.id == id)
+                    .map(|item| (*status, item))
+            })
+        };
+
+        // Drop the lines of deleted items
+        self.lines.retain(|line| match line {
+            Line::Task { id, .. } => find(*id).is_some(),
+            Line::Text(_) => true,
+        });
+        self.reorder(lists);
+
+        let known: Vec<ItemId> = self
+            .lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Task { id, .. } => Some(*id),
+                Line::Text(_) => None,
+            })
+            .collect();
+        let insert_at = self
+            .lines
+            .iter()
+            .rposition(|line| matches!(line, Line::Task { .. }))
+            .map_or(self.lines.len(), |index| index + 1);
+        let new_lines: Vec<Line> = lists
+            .iter()
+            .flat_map(|(_, items)| items.iter())
+            .filter(|item| !known.contains(&item.id))
+            .map(|item| Line::Task {
+                id: item.id,
+                prefix: "- ".to_string(),
+                checked: 'x',
+                status: Status::Todo,
+            })
+            .collect();
+        self.lines.splice(insert_at..insert_at, new_lines);
+
+        let mut text = String::new();
+        for line in self.lines.iter_mut() {
+            match line {
+                Line::Text(line) => text.push_str(line),
+                Line::Task {
+                    id,
+                    prefix,
+                    checked,
+                    status,
+                } => {
+                    if let Some((item_status, item)) = find(*id) {
+                        let mark = match item_status {
+                            Status::Todo => ' ',
+                            Status::Done => *checked,
+                        };
+                        text.push_str(&format!("{}[{}] {}", prefix, mark, format_text(item)));
+                        let indent = " ".repeat(prefix.len());
+                        for note in item.notes.lines() {
+                            text.push('\n');
+                            text.push_str(&indent);
+                            text.push_str(note);
+                        }
+                        *status = item_status;
+                    }
+                }
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    // Puts the items that are still in the list they were read from into the
+    // task lines of that list in list order, so moving an item within its
+    // list moves it in the file. Items that changed lists keep their line.
+    fn reorder(&mut self, lists: &[(Status, &[Item])]) {
+        for (status, items) in lists {
+            let slots: Vec<usize> = (0..self.lines.len())
+                .filter(|&index| match &self.lines[index] {
+                    Line::Task { id, status: was, .. } => {
+                        was == status && items.iter().any(|item| item.id == *id)
+                    }
+                    Line::Text(_) => false,
+                })
+                .collect();
+            let ids: Vec<ItemId> = slots
+                .iter()
+                .filter_map(|&index| match &self.lines[index] {
+                    Line::Task { id, .. } => Some(*id),
+                    Line::Text(_) => None,
+                })
+                .collect();
+            let ordered = items.iter().map(|item| item.id).filter(|id| ids.contains(id));
+            for (index, ordered_id) in slots.into_iter().zip(ordered) {
+                if let Line::Task { id, .. } = &mut self.lines[index] {
+                    *id = ordered_id;
+                }
+            }
+        }
+    }
+}
+
+// Item of the text of a task line, with the priority, tags and due date taken
+// out of it
+fn parse_text(text: &str) -> Item {
+    let mut item = Item::default();
+    let mut rest = text;
+    let bytes = rest.as_bytes();
+    if bytes.len() > 4 && bytes[0] == b'(' && bytes[1].is_ascii_uppercase() && &rest[2..4] == ") "
+    {
+        item.priority = Some(bytes[1] as char);
+        rest = &rest[4..];
+    }
+    let mut words: Vec<&str> = rest.split(' ').collect();
+    while words.len() > 1 {
+        let word = words[words.len() - 1];
+        if let Some(tag) = parse_tag(word) {
+            item.tags.insert(0, tag);
+        } else if let Some(due) = word.strip_prefix("due:").filter(|_| item.due.is_none()) {
+            match item::parse_date(due) {
+                Ok(due) => item.due = Some(due),
+                Err(_) => break,
+            }
+        } else {
+            break;
+        }
+        words.pop();
+    }
+    item.title = words.join(" ");
+    item
+}
+
+// The text of the task line of `item`, the reverse of parse_text()
+fn format_text(item: &Item) -> String {
+    let mut text = String::new();
+    if let Some(priority) = item.priority {
+        text.push_str(&format!("({}) ", priority));
+    }
+    text.push_str(&item.title);
+    for tag in item.tags.iter() {
+        if tag.starts_with('@') {
+            text.push_str(&format!(" {}", tag));
+        } else {
+            text.push_str(&format!(" #{}", tag.trim_start_matches(['#', '+'])));
+        }
+    }
+    if let Some(due) = item.due {
+        text.push_str(&format!(" due:{}", due.format(item::DATE_FORMAT)));
+    }
+    text
+}
+
+// `#tag` starting with a letter, so issue numbers like #12 stay in the title,
+// or `@context`
+fn parse_tag(word: &str) -> Option<String> {
+    if let Some(tag) = word.strip_prefix('#') {
+        tag.starts_with(char::is_alphabetic).then(|| tag.to_string())
+    } else {
+        (word.len() > 1 && word.starts_with('@')).then(|| word.to_string())
+    }
+}
+
+pub fn is_task(line: &str) -> bool {
+    parse_task(line).is_some()
+}
+
+fn parse_heading(line: &str) -> Option<&str> {
+    let text = line.trim_start_matches('#');
+    let level = line.len() - text.len();
+    if (1..=6).contains(&level) && text.starts_with(' ') {
+        Some(text.trim())
+    } else {
+        None
+    }
+}
+
+// Splits "  - [x] title" into ("  - ", Some('x'), "title")
+fn parse_task(line: &str) -> Option<(&str, Option<char>, &str)> {
+    let text = line.trim_start();
+    let indent = line.len() - text.len();
+
+    let marker_len = if text.starts_with("- ") || text.starts_with("* ") || text.starts_with("+ ")
+    {
+        2
+    } else {
+        let digits = text.chars().take_while(char::is_ascii_digit).count();
+        let rest = &text[digits..];
+        if digits > 0 && (rest.starts_with(". ") || rest.starts_with(") ")) {
+            digits + 2
+        } else {
+            return None;
+        }
+    };
+
+    let prefix_len = indent + marker_len;
+    let rest = &line[prefix_len..];
+    let checked = match rest.get(..4)? {
+        "[ ] " => None,
+        "[x] " => Some('x'),
+        "[X] " => Some('X'),
+        _ => return None,
+    };
+    Some((&line[..prefix_len], checked, &rest[4..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Parses `text`, lets `change` edit the lists and renders the document again
+    fn resave(text: &str, change: impl FnOnce(&mut Vec<Item>, &mut Vec<Item>)) -> String {
+        let mut next_id = 0;
+        let (mut document, items) = Document::parse(text, || {
+            next_id += 1;
+            next_id
+        });
+        let list = |status: Status| -> Vec<Item> {
+            let items = items.iter().filter(|(known, _)| *known == status);
+            items.map(|(_, item)| item.clone()).collect()
+        };
+        let (mut todo, mut done) = (list(Status::Todo), list(Status::Done));
+        change(&mut todo, &mut done);
+        document.render(&[(Status::Todo, &todo), (Status::Done, &done)])
+    }
+
+    #[test]
+    fn metadata_round_trips() {
+        let text = "# Plan\n\
+                    Prose.\n\
+                    - [ ] (A) Rotate keys #security @ops due:2024-03-01\n  \
+                      Ask Bob first.\n    \
+                        - not a task\n\
+                    - [x] Fix #12 properly\n";
+        let (_, items) = Document::parse(text, || 1);
+        let keys = &items[0].1;
+        assert_eq!(keys.title, "Rotate keys");
+        assert_eq!(keys.priority, Some('A'));
+        assert_eq!(keys.tags, ["security", "@ops"]);
+        assert_eq!(keys.due, item::parse_date("2024-03-01").ok());
+        assert_eq!(keys.notes, "Ask Bob first.\n  - not a task");
+        assert_eq!(items[1].1.title, "Fix #12 properly");
+
+        assert_eq!(resave(text, |_, _| {}), text);
+    }
+
+    #[test]
+    fn edited_metadata_is_saved() {
+        let saved = resave("- [ ] Buy milk\n", |todo, _| {
+            todo[0].priority = Some('B');
+            todo[0].tags = vec!["home".to_string()];
+            todo[0].due = item::parse_date("2024-03-02").ok();
+            todo[0].notes = "oat milk\nor soy".to_string();
+        });
+        assert_eq!(saved, "- [ ] (B) Buy milk #home due:2024-03-02\n  oat milk\n  or soy\n");
+    }
+
+    #[test]
+    fn moved_items_are_saved_in_list_order() {
+        let text = "- [ ] a\n- [x] b\n- [ ] c\n";
+        let moved = resave(text, |todo, _| todo.reverse());
+        assert_eq!(moved, "- [ ] c\n- [x] b\n- [ ] a\n");
+
+        // A finished item keeps its line
+        let finished = resave(text, |todo, done| done.push(todo.remove(0)));
+        assert_eq!(finished, "- [x] a\n- [x] b\n- [ ] c\n");
+    }
+}
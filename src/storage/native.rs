@@ -0,0 +1,240 @@
+// The app's own format: one `TODO: `/`DONE: ` line per item, followed by its
+// metadata as indented `key: value` lines
+//
+//     TODO: Buy milk
+//         id: 3
+//         due: 2024-03-01
+//
+// Lines that can't be parsed are written back below the item they followed,
+// together with the metadata lines below them.
+
+use std::fmt::Write;
+
+use anyhow::Result;
+
+use super::{Loaded, ParseError, Storage, StorageFile};
+use crate::item::{Item, ItemId};
+use crate::Status;
+
+pub struct NativeStorage {
+    file: StorageFile,
+    // lines that couldn't be parsed with the item above them, None at the top
+    // of the file
+    unparsed: Vec<(Option<ItemId>, String)>,
+    // the items in the order they were read
+    order: Vec<ItemId>,
+}
+
+impl NativeStorage {
+    pub fn new(file: StorageFile) -> Self {
+        Self {
+            file,
+            unparsed: Vec::new(),
+            order: Vec::new(),
+        }
+    }
+}
+
+impl Storage for NativeStorage {
+    fn load(&mut self) -> Result<Loaded> {
+        let text = self.file.read()?;
+        let mut loaded = Loaded::default();
+        // like `unparsed` but with the index of the item in `loaded`
+        let mut unparsed = Vec::new();
+        // whether the last item line couldn't be parsed, its metadata goes with it
+        let mut in_unparsed = false;
+        for (index, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let above = loaded.items.len().checked_sub(1);
+            let indented = line.starts_with(char::is_whitespace);
+            if indented && in_unparsed {
+                unparsed.push((above, line.to_string()));
+                continue;
+            }
+            in_unparsed = false;
+
+            // Indented lines carry the metadata of the item above them
+            let result = if indented {
+                match (loaded.items.last_mut(), parse_field(line.trim())) {
+                    (Some((_, item)), Some((key, value))) => item.set_field(key, value),
+                    (None, _) => Err("metadata without an item".to_string()),
+                    (_, None) => Err("ill-formed metadata line".to_string()),
+                }
+            } else {
+                match parse_item(line.trim()) {
+                    Some((status, title)) => {
+                        loaded
+                            .items
+                            .push((status, Item::from_title(title.trim_end())));
+                        Ok(())
+                    }
+                    None => {
+                        in_unparsed = true;
+                        Err("ill-formed item line".to_string())
+                    }
+                }
+            };
+
+            if let Err(reason) = result {
+                loaded.errors.push(ParseError {
+                    path: self.file.path.clone(),
+                    line: index + 1,
+                    reason,
+                    text: line.to_string(),
+                });
+                unparsed.push((above, line.to_string()));
+            }
+        }
+
+        // Unparsed lines refer to items by id, items without one get the next free ones
+        let mut next_id = loaded.items.iter().map(|(_, item)| item.id).max().unwrap_or(0);
+        for (_, item) in loaded.items.iter_mut().filter(|(_, item)| item.id == 0) {
+            next_id += 1;
+            item.id = next_id;
+        }
+        self.order = loaded.items.iter().map(|(_, item)| item.id).collect();
+        self.unparsed = unparsed
+            .into_iter()
+            .map(|(above, line)| (above.map(|index| self.order[index]), line))
+            .collect();
+        Ok(loaded)
+    }
+
+    // The metadata lines of an imported item line are dropped with it
+    fn forget_errors(&mut self) {
+        self.unparsed.clear();
+    }
+
+    fn save(&mut self, lists: &[(Status, &[Item])]) -> Result<()> {
+        let saved: Vec<ItemId> = lists
+            .iter()
+            .flat_map(|(_, items)| items.iter().map(|item| item.id))
+            .collect();
+        // Lines below a deleted item move up to the closest item above it that is left
+        let unparsed: Vec<(Option<ItemId>, &str)> = self
+            .unparsed
+            .iter()
+            .map(|(above, line)| {
+                let index = above.and_then(|id| self.order.iter().position(|known| *known == id));
+                let above = index.and_then(|index| {
+                    self.order[..=index]
+                        .iter()
+                        .rev()
+                        .find(|id| saved.contains(id))
+                        .copied()
+                });
+                (above, line.as_str())
+            })
+            .collect();
+        let write_unparsed = |text: &mut String, above: Option<ItemId>| -> Result<()> {
+            for (_, line) in unparsed.iter().filter(|(known, _)| *known == above) {
+                writeln!(text, "{}", line)?;
+            }
+            Ok(())
+        };
+
+        let mut text = String::new();
+        write_unparsed(&mut text, None)?;
+        for (status, items) in lists {
+            let prefix = match status {
+                Status::Todo => "TODO: ",
+                Status::Done => "DONE: ",
+            };
+            for item in items.iter() {
+                writeln!(text, "{}{}", prefix, item.title)?;
+                for (key, value) in item.fields() {
+                    writeln!(text, "    {}: {}", key, value)?;
+                }
+                write_unparsed(&mut text, Some(item.id))?;
+            }
+        }
+        self.file.write(&text)
+    }
+
+    fn watch(&mut self) -> Result<bool> {
+        Ok(self.file.changed())
+    }
+}
+
+// `TODO: title` or `DONE: title`, a bare `TODO:` is an item left without a
+// title (lines are trimmed before they get here)
+pub fn parse_item(line: &str) -> Option<(Status, &str)> {
+    [("TODO:", Status::Todo), ("DONE:", Status::Done)]
+        .into_iter()
+        .find_map(|(prefix, status)| match line.strip_prefix(prefix)? {
+            "" => Some((status, "")),
+            rest => rest.strip_prefix(' ').map(|title| (status, title)),
+        })
+}
+
+fn parse_field(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once(':')?;
+    Some((key.trim(), value.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::storage::scratch_dir;
+
+    // Loads `text`, lets `change` edit the lists and returns the saved file
+    fn resave(name: &str, text: &str, change: impl FnOnce(&mut Vec<(Status, Item)>)) -> String {
+        let path = scratch_dir(name).join("todo.txt");
+        fs::write(&path, text).unwrap();
+        let mut storage = NativeStorage::new(StorageFile::new(path.to_str().unwrap()));
+        let mut items = storage.load().unwrap().items;
+        change(&mut items);
+        let list = |status: Status| -> Vec<Item> {
+            let items = items.iter().filter(|(known, _)| *known == status);
+            items.map(|(_, item)| item.clone()).collect()
+        };
+        let (todo, done) = (list(Status::Todo), list(Status::Done));
+        storage.save(&[(Status::Todo, &todo), (Status::Done, &done)]).unwrap();
+        fs::read_to_string(&path).unwrap()
+    }
+
+    #[test]
+    fn metadata_below_a_bad_line_stays_with_it() {
+        let text = "TODO: a\n    id: 1\nGARBAGE\n    due: 2020-01-01\nTODO: b\n    id: 2\n";
+        let path = scratch_dir("native-bad-line").join("todo.txt");
+        fs::write(&path, text).unwrap();
+        let loaded = NativeStorage::new(StorageFile::new(path.to_str().unwrap())).load().unwrap();
+        assert_eq!(loaded.items[0].1.due, None);
+        assert_eq!(loaded.errors.len(), 1);
+        assert_eq!(loaded.errors[0].line, 3);
+
+        assert_eq!(resave("native-unchanged", text, |_| {}), text);
+    }
+
+    #[test]
+    fn unparsed_lines_keep_their_place() {
+        let text = "# notes\nTODO: a\nDONE: b\n???\nTODO: c\n";
+        let saved = resave("native-place", text, |_| {});
+        assert_eq!(
+            saved,
+            "# notes\nTODO: a\n    id: 1\nTODO: c\n    id: 3\nDONE: b\n    id: 2\n???\n"
+        );
+        // below a deleted item they move up to the item above it
+        let saved = resave("native-deleted", text, |items| {
+            items.remove(1);
+        });
+        assert_eq!(saved, "# notes\nTODO: a\n    id: 1\n???\nTODO: c\n    id: 3\n");
+    }
+
+    #[test]
+    fn items_without_a_title() {
+        let text = "TODO: \n    id: 1\nTODO: b\n    id: 2\n";
+        let path = scratch_dir("native-empty-title").join("todo.txt");
+        fs::write(&path, text).unwrap();
+        let loaded = NativeStorage::new(StorageFile::new(path.to_str().unwrap())).load().unwrap();
+        assert!(loaded.errors.is_empty());
+        let titles: Vec<_> = loaded.items.iter().map(|(_, item)| item.title.as_str()).collect();
+        assert_eq!(titles, ["", "b"]);
+
+        assert_eq!(resave("native-empty-title-saved", text, |_| {}), text);
+    }
+}
@@ -0,0 +1,393 @@
+// Reading and writing of the todo.txt format, see https://github.com/todotxt/todo.txt
+//
+//     x (A) 2024-03-02 2024-03-01 Call mom +family @phone due:2024-03-05
+//
+// The description is kept as the item title word for word, so +project,
+// @context and `key:value` tokens stay where they are. Projects and contexts
+// are mirrored into the item tags (projects without their `+`) and the first
+// valid `due:` sets the due date, editing the description changes them too.
+// Tags, a due date and extensions that items from other formats bring along
+// are added at the end of the description.
+//
+// Like the Markdown format the file keeps its line order: items are saved
+// where they were read, blank lines stay where they are and new items are
+// added at the end. Files with CRLF line endings keep them.
+
+use anyhow::Result;
+use chrono::{NaiveDate, NaiveDateTime};
+
+use super::{Loaded, Storage, StorageFile};
+use crate::item::{self, Item, ItemId};
+use crate::Status;
+
+pub struct TodoTxtStorage {
+    file: StorageFile,
+    document: Document,
+}
+
+impl TodoTxtStorage {
+    pub fn new(file: StorageFile) -> Self {
+        Self {
+            file,
+            document: Document::default(),
+        }
+    }
+}
+
+impl Storage for TodoTxtStorage {
+    fn load(&mut self) -> Result<Loaded> {
+        let text = self.file.read()?;
+        // As in markdown.rs the file is the only source of items, numbering
+        // them from 1 is enough for the document to refer to them
+        let mut items = Vec::new();
+        let mut lines = Vec::new();
+        for line in text.lines() {
+            if line.trim().is_empty() {
+                lines.push(Line::Blank(line.to_string()));
+                continue;
+            }
+            let (status, mut item) = parse_line(line);
+            item.id = items.len() as ItemId + 1;
+            lines.push(Line::Item(item.id));
+            items.push((status, item));
+        }
+        self.document = Document {
+            lines,
+            crlf: text.contains("\r\n"),
+        };
+        Ok(items.into())
+    }
+
+    fn save(&mut self, lists: &[(Status, &[Item])]) -> Result<()> {
+        let text = self.document.render(lists);
+        self.file.write(&text)
+    }
+
+    fn keeps_ids(&self) -> bool {
+        false
+    }
+
+    fn metadata_in_titles(&self) -> bool {
+        true
+    }
+
+    fn watch(&mut self) -> Result<bool> {
+        Ok(self.file.changed())
+    }
+}
+
+// The lines of the file, items referred to by id
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    lines: Vec<Line>,
+    // the file's lines end in "\r\n" instead of "\n"
+    crlf: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+    Item(ItemId),
+    // empty or only whitespace, written back as it was
+    Blank(String),
+}
+
+impl Document {
+    // Renders the items of `lists` in file order, items that weren't in the
+    // file go at the end. Remembers the new order for the next save.
+    pub fn render(&mut self, lists: &[(Status, &[Item])]) -> String {
+        let all: Vec<(Status, &Item)> = lists
+            .iter()
+            .flat_map(|(status, items)| items.iter().map(move |item| (*status, item)))
+            .collect();
+        let find = |id: ItemId| all.iter().find(|(_, item)| item.id == id);
+
+        self.lines.retain(|line| match line {
+            Line::Item(id) => find(*id).is_some(),
+            Line::Blank(_) => true,
+        });
+        for (_, item) in all.iter() {
+            if !self.lines.contains(&Line::Item(item.id)) {
+                self.lines.push(Line::Item(item.id));
+            }
+        }
+
+        let newline = if self.crlf { "\r\n" } else { "\n" };
+        let mut text = String::new();
+        for line in self.lines.iter() {
+            match line {
+                Line::Item(id) => {
+                    if let Some((status, item)) = find(*id) {
+                        text.push_str(&format_item(item, *status));
+                    }
+                }
+                Line::Blank(blank) => text.push_str(blank),
+            }
+            text.push_str(newline);
+        }
+        text
+    }
+}
+
+pub fn parse_line(line: &str) -> (Status, Item) {
+    let mut item = Item::default();
+    let mut rest = line;
+
+    let status = match rest.strip_prefix("x ") {
+        Some(tail) => {
+            rest = tail;
+            Status::Done
+        }
+        None => Status::Todo,
+    };
+
+    if let Some((priority, tail)) = split_priority(rest) {
+        item.priority = Some(priority);
+        rest = tail;
+    }
+
+    // A completed task may carry a completion date followed by a creation date,
+    // an open task only a creation date.
+    let (first, tail) = split_date(rest);
+    rest = tail;
+    match (status, first) {
+        (Status::Done, Some(completed)) => {
+            item.completed = Some(midnight(completed));
+            let (second, tail) = split_date(rest);
+            rest = tail;
+            item.created = second.map(midnight);
+        }
+        (_, created) => item.created = created.map(midnight),
+    }
+
+    retitle(&mut item, rest);
+    (status, item)
+}
+
+// Gives `item` the description `title` and takes the tags and the due date
+// from it. Those of the old title go, tags that weren't in it came from
+// another format and stay.
+pub fn retitle(item: &mut Item, title: &str) {
+    let old_words: Vec<&str> = item.title.split(' ').collect();
+    item.tags.retain(|tag| !old_words.contains(&tag_token(tag).as_str()));
+    if due_token(&item.title).is_some() {
+        item.due = None;
+    }
+    for word in title.split(' ') {
+        if let Some(tag) = parse_tag(word) {
+            if !item.tags.contains(&tag) {
+                item.tags.push(tag);
+            }
+        }
+    }
+    if let Some(("due", value)) = due_token(title).and_then(parse_extension) {
+        item.due = item::parse_date(value).ok();
+    }
+    item.title = title.to_string();
+}
+
+pub fn format_item(item: &Item, status: Status) -> String {
+    let mut line = String::new();
+    if status == Status::Done {
+        line.push_str("x ");
+    }
+    if let Some(priority) = item.priority {
+        line.push_str(&format!("({}) ", priority));
+    }
+    if status == Status::Done {
+        if let Some(completed) = item.completed {
+            line.push_str(&format!("{} ", completed.format(item::DATE_FORMAT)));
+        }
+    }
+    if let Some(created) = item.created {
+        line.push_str(&format!("{} ", created.format(item::DATE_FORMAT)));
+    }
+    line.push_str(&item.title);
+
+    let words: Vec<&str> = item.title.split(' ').collect();
+    let has_key = |key: &str| {
+        words
+            .iter()
+            .any(|word| parse_extension(word).is_some_and(|(known, _)| known == key))
+    };
+    for tag in item.tags.iter() {
+        let token = tag_token(tag);
+        if !words.contains(&token.as_str()) {
+            line.push(' ');
+            line.push_str(&token);
+        }
+    }
+    if let Some(due) = item.due.filter(|_| !has_key("due")) {
+        line.push_str(&format!(" due:{}", due.format(item::DATE_FORMAT)));
+    }
+    for (key, value) in item.extensions.iter().filter(|(key, _)| !has_key(key)) {
+        line.push_str(&format!(" {}:{}", key, value));
+    }
+    line
+}
+
+// The due date token the title of a todo.txt item has, if any
+pub fn due_token(title: &str) -> Option<&str> {
+    title
+        .split(' ')
+        .find(|word| matches!(parse_extension(word), Some(("due", _))))
+}
+
+// The todo.txt spelling of a tag: contexts keep their `@`, everything else is a +project
+pub fn tag_token(tag: &str) -> String {
+    if tag.starts_with('@') {
+        tag.to_string()
+    } else {
+        format!("+{}", tag)
+    }
+}
+
+fn parse_tag(word: &str) -> Option<String> {
+    if word.len() < 2 {
+        return None;
+    }
+    if let Some(project) = word.strip_prefix('+') {
+        Some(project.to_string())
+    } else if word.starts_with('@') {
+        Some(word.to_string())
+    } else {
+        None
+    }
+}
+
+// `key:value` where neither side is empty or contains another colon. URLs like
+// https://example.com and times like 12:30 stay plain words.
+fn parse_extension(word: &str) -> Option<(&str, &str)> {
+    let (key, value) = word.split_once(':')?;
+    if key.is_empty() || value.is_empty() || value.contains(':') || value.starts_with("//") {
+        return None;
+    }
+    if key.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((key, value))
+}
+
+fn split_priority(text: &str) -> Option<(char, &str)> {
+    let bytes = text.as_bytes();
+    if bytes.len() >= 4
+        && bytes[0] == b'('
+        && bytes[1].is_ascii_uppercase()
+        && bytes[2] == b')'
+        && bytes[3] == b' '
+    {
+        Some((bytes[1] as char, &text[4..]))
+    } else {
+        None
+    }
+}
+
+fn split_date(text: &str) -> (Option<NaiveDate>, &str) {
+    let (word, tail) = text.split_once(' ').unwrap_or((text, ""));
+    match item::parse_date(word) {
+        Ok(date) if word.len() == 10 => (Some(date), tail),
+        _ => (None, text),
+    }
+}
+
+fn midnight(date: NaiveDate) -> NaiveDateTime {
+    date.and_hms_opt(0, 0, 0).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::storage::scratch_dir;
+
+    fn date(text: &str) -> NaiveDate {
+        item::parse_date(text).unwrap()
+    }
+
+    #[test]
+    fn lines_round_trip() {
+        let lines = [
+            "Meeting at 12:30 with Bob +work due:2024-03-05 @office",
+            "x (A) 2024-03-02 2024-03-01 Call mom +family @phone due:2024-03-05",
+            "x 2024-03-02 Pay rent",
+            "(B) 2024-01-01 Read https://example.com/a:b later",
+            "t:2024-04-01 Plan +trip rec:+1w +trip due:nonsense",
+            "Ratio 3:2 and key:value key:other",
+        ];
+        for line in lines {
+            let (status, item) = parse_line(line);
+            assert_eq!(format_item(&item, status), line);
+        }
+    }
+
+    #[test]
+    fn tags_and_due_date_come_from_the_description() {
+        let (status, item) = parse_line("Meeting at 12:30 +work due:2024-03-05 @office +work");
+        assert_eq!(status, Status::Todo);
+        assert_eq!(item.title, "Meeting at 12:30 +work due:2024-03-05 @office +work");
+        assert_eq!(item.tags, ["work", "@office"]);
+        assert_eq!(item.due, Some(date("2024-03-05")));
+        assert!(item.extensions.is_empty());
+    }
+
+    #[test]
+    fn tags_and_due_date_follow_the_title() {
+        let line = "(A) 2024-01-01 Meeting +work @office due:2024-03-05";
+        let (status, mut item) = parse_line(line);
+        item.tags.push("@imported".to_string());
+        retitle(&mut item, "Meeting moved");
+        assert_eq!(item.tags, ["@imported"]);
+        assert_eq!(item.due, None);
+        assert_eq!(format_item(&item, status), "(A) 2024-01-01 Meeting moved @imported");
+
+        retitle(&mut item, "Meeting moved +work due:2024-03-12");
+        assert_eq!(item.tags, ["@imported", "work"]);
+        assert_eq!(item.due, Some(date("2024-03-12")));
+        let (_, reloaded) = parse_line(&format_item(&item, status));
+        assert_eq!(reloaded.title, "Meeting moved +work due:2024-03-12 @imported");
+        assert_eq!(reloaded.due, item.due);
+    }
+
+    #[test]
+    fn metadata_from_other_formats_is_appended() {
+        let mut item = Item::from_title("Buy milk +home");
+        item.tags = vec!["home".to_string(), "@shop".to_string()];
+        item.due = Some(date("2024-03-05"));
+        item.extensions = vec![("rec".to_string(), "1w".to_string())];
+        assert_eq!(
+            format_item(&item, Status::Todo),
+            "Buy milk +home @shop due:2024-03-05 rec:1w"
+        );
+    }
+
+    #[test]
+    fn saving_keeps_the_line_order() {
+        let lines = ["x Pay rent", "Buy milk", "x Call mom", "Write report"];
+        let mut items: Vec<(Status, Item)> = lines.iter().map(|line| parse_line(line)).collect();
+        for (index, (_, item)) in items.iter_mut().enumerate() {
+            item.id = index as ItemId + 1;
+        }
+        let lines = (1..=4).map(Line::Item).collect();
+        let mut document = Document { lines, crlf: false };
+        let mut new = Item::from_title("New item");
+        new.id = 5;
+        // Buy milk done, Write report deleted
+        let todo = vec![new];
+        let done = vec![items[0].1.clone(), items[2].1.clone(), items[1].1.clone()];
+        let text = document.render(&[(Status::Todo, &todo), (Status::Done, &done)]);
+        assert_eq!(text, "x Pay rent\nx Buy milk\nx Call mom\nNew item\n");
+    }
+
+    #[test]
+    fn blank_lines_and_line_endings_are_kept() {
+        let path = scratch_dir("todotxt-blank-lines").join("todo.txt");
+        let text = "Buy milk\r\n\r\n  \r\nx Pay rent\r\nCall mom\r\n";
+        fs::write(&path, text).unwrap();
+        let mut storage = TodoTxtStorage::new(StorageFile::new(path.to_str().unwrap()));
+        let loaded = storage.load().unwrap().items;
+        let items: Vec<_> = loaded.into_iter().map(|(_, item)| item).collect();
+        let (todo, done) = ([items[0].clone(), items[2].clone()], [items[1].clone()]);
+        storage.save(&[(Status::Todo, &todo), (Status::Done, &done)]).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+    }
+}
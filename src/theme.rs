@@ -0,0 +1,274 @@
+// Colours of everything on screen, picked by the `theme` setting (dark, light
+// or high-contrast) and changed per role in the `[colors]` table of the
+// config files:
+//
+//     theme = "light"
+//
+//     [colors]
+//     header = { fg = "dark-blue" }
+//     selected = { fg = "white", bg = "#005f87" }
+//     overdue = { fg = "196", attrs = ["bold"] }
+//
+// Colours are one of the 16 names in COLOR_NAMES, a number 0-255 of the
+// 256-colour palette, `#rrggbb` for truecolor or `reset` for the terminal's
+// own colour. `attrs` replaces the role's text attributes with any of bold,
+// italic, underline, strikethrough, dim and reverse, `[]` for none.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use crossterm::style::Color;
+use serde::Deserialize;
+
+use crate::screen_buf::Attrs;
+
+pub const PRESETS: &[&str] = &["dark", "light", "high-contrast"];
+
+const COLOR_NAMES: &[(&str, Color)] = &[
+    ("black", Color::Black),
+    ("dark-red", Color::DarkRed),
+    ("dark-green", Color::DarkGreen),
+    ("dark-yellow", Color::DarkYellow),
+    ("dark-blue", Color::DarkBlue),
+    ("dark-magenta", Color::DarkMagenta),
+    ("dark-cyan", Color::DarkCyan),
+    ("grey", Color::Grey),
+    ("dark-grey", Color::DarkGrey),
+    ("red", Color::Red),
+    ("green", Color::Green),
+    ("yellow", Color::Yellow),
+    ("blue", Color::Blue),
+    ("magenta", Color::Magenta),
+    ("cyan", Color::Cyan),
+    ("white", Color::White),
+    ("reset", Color::Reset),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub attrs: Attrs,
+}
+
+const fn style(fg: Color, bg: Color) -> Style {
+    Style { fg, bg, attrs: Attrs::empty() }
+}
+
+impl Style {
+    const fn with(self, attrs: Attrs) -> Self {
+        Style { attrs, ..self }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    // items and the empty screen
+    pub normal: Style,
+    pub header: Style,
+    // the cursor in the active list
+    pub selected: Style,
+    // the cursor in the other list
+    pub selected_inactive: Style,
+    pub done: Style,
+    pub overdue: Style,
+    pub tag: Style,
+    pub search_match: Style,
+    pub status_bar: Style,
+    pub status_error: Style,
+    pub edit_caret: Style,
+}
+
+const ROLES: &[&str] = &[
+    "normal",
+    "header",
+    "selected",
+    "selected-inactive",
+    "done",
+    "overdue",
+    "tag",
+    "search-match",
+    "status-bar",
+    "status-error",
+    "edit-caret",
+];
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+// A `[colors]` entry, what it leaves out stays as the preset has it
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StyleSpec {
+    fg: Option<String>,
+    bg: Option<String>,
+    attrs: Option<Vec<String>>,
+}
+
+pub type ColorTable = BTreeMap<String, StyleSpec>;
+
+impl Theme {
+    pub fn dark() -> Self {
+        use Color::*;
+        Self {
+            normal: style(White, Black),
+            header: style(Cyan, Black).with(Attrs::BOLD),
+            selected: style(Black, White),
+            selected_inactive: style(White, DarkGrey),
+            done: style(Grey, Black).with(Attrs::STRIKETHROUGH),
+            overdue: style(Red, Black).with(Attrs::UNDERLINE),
+            tag: style(Magenta, Black),
+            search_match: style(Black, Yellow),
+            status_bar: style(Black, White),
+            status_error: style(White, Red),
+            edit_caret: style(Black, White),
+        }
+    }
+
+    pub fn light() -> Self {
+        use Color::*;
+        Self {
+            normal: style(Black, White),
+            header: style(DarkBlue, White).with(Attrs::BOLD),
+            selected: style(White, DarkBlue),
+            selected_inactive: style(Black, Grey),
+            done: style(DarkGrey, White).with(Attrs::STRIKETHROUGH),
+            overdue: style(DarkRed, White).with(Attrs::UNDERLINE),
+            tag: style(DarkMagenta, White),
+            search_match: style(Black, Yellow),
+            status_bar: style(White, DarkBlue),
+            status_error: style(White, DarkRed),
+            edit_caret: style(White, Black),
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        use Color::*;
+        Self {
+            normal: style(White, Black),
+            header: style(Yellow, Black).with(Attrs::BOLD),
+            selected: style(Black, Yellow),
+            selected_inactive: style(Black, White),
+            done: style(White, Black).with(Attrs::STRIKETHROUGH),
+            overdue: style(Black, Red).with(Attrs::UNDERLINE),
+            tag: style(Cyan, Black),
+            search_match: style(Black, Cyan),
+            status_bar: style(Black, White),
+            status_error: style(White, DarkRed),
+            edit_caret: style(Black, Yellow),
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "high-contrast" => Some(Self::high_contrast()),
+            _ => None,
+        }
+    }
+
+    // The preset `name` with the `[colors]` tables of the config files applied in order
+    pub fn build(name: &str, tables: &[(PathBuf, ColorTable)]) -> Result<Self> {
+        let mut theme = Self::preset(name).ok_or_else(|| {
+            anyhow!("unknown theme `{}`, expected one of {}", name, PRESETS.join(", "))
+        })?;
+        for (path, table) in tables {
+            theme.apply(table, path)?;
+        }
+        Ok(theme)
+    }
+
+    fn apply(&mut self, table: &ColorTable, path: &Path) -> Result<()> {
+        let mut errors = Vec::new();
+        for (role, spec) in table {
+            let Some(style) = self.role_mut(role) else {
+                errors.push(format!("unknown role `{}`", role));
+                continue;
+            };
+            for (color, value) in [(&mut style.fg, &spec.fg), (&mut style.bg, &spec.bg)] {
+                if let Some(value) = value {
+                    match parse_color(value) {
+                        Ok(parsed) => *color = parsed,
+                        Err(message) => errors.push(format!("`{}`: {}", role, message)),
+                    }
+                }
+            }
+            if let Some(names) = &spec.attrs {
+                style.attrs = Attrs::empty();
+                for name in names {
+                    match parse_attr(name) {
+                        Ok(attr) => style.attrs |= attr,
+                        Err(message) => errors.push(format!("`{}`: {}", role, message)),
+                    }
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(anyhow!(
+                "{}: invalid colors:\n    {}\n    (roles: {})",
+                path.display(),
+                errors.join("\n    "),
+                ROLES.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    fn role_mut(&mut self, role: &str) -> Option<&mut Style> {
+        let style = match role {
+            "normal" => &mut self.normal,
+            "header" => &mut self.header,
+            "selected" => &mut self.selected,
+            "selected-inactive" => &mut self.selected_inactive,
+            "done" => &mut self.done,
+            "overdue" => &mut self.overdue,
+            "tag" => &mut self.tag,
+            "search-match" => &mut self.search_match,
+            "status-bar" => &mut self.status_bar,
+            "status-error" => &mut self.status_error,
+            "edit-caret" => &mut self.edit_caret,
+            _ => return None,
+        };
+        Some(style)
+    }
+}
+
+// The name of one of the Attrs flags, like `bold`
+pub fn parse_attr(name: &str) -> Result<Attrs, String> {
+    Attrs::from_name(&name.trim().to_uppercase()).ok_or_else(|| {
+        let names: Vec<_> = Attrs::all()
+            .iter_names()
+            .map(|(known, _)| known.to_lowercase())
+            .collect();
+        format!("unknown attribute `{}`, expected one of {}", name, names.join(", "))
+    })
+}
+
+// A name, 0-255 or #rrggbb
+pub fn parse_color(value: &str) -> Result<Color, String> {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        let channel = |at: usize| {
+            hex.get(at..at + 2)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+        };
+        return match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Color::Rgb { r, g, b }),
+            _ => Err(format!("invalid colour `{}`, expected #rrggbb", value)),
+        };
+    }
+    if let Ok(index) = value.parse::<u8>() {
+        return Ok(Color::AnsiValue(index));
+    }
+    let name = value.to_lowercase().replace('_', "-").replace("gray", "grey");
+    COLOR_NAMES
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, color)| *color)
+        .ok_or_else(|| format!("unknown colour `{}`", value))
+}
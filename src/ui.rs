@@ -4,9 +4,16 @@ use crossterm::terminal::{Clear, ClearType};
 use crossterm::{queue, ExecutableCommand, QueueableCommand};
 use std::cmp;
 use std::io::{self, stderr, stdout, BufRead, Write};
-use std::ops::{Add, Div, Mul, Sub};
+use std::ops::{Add, Div, Mul, Range, Sub};
 
-use crate::screen_buf::{apply_patches, VirtualScreen};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::backend::Backend;
+use crate::screen_buf::{apply_patches, fit, grapheme_width, text_width, Attrs, VirtualScreen};
+use crate::theme::Style;
+
+// Byte range of a label and the style it is drawn in
+pub type Span = (Range<usize>, Style);
 
 #[derive(Default, Copy, Clone)]
 pub struct Vec2 {
@@ -82,25 +89,41 @@ impl Layout {
 }
 
 #[derive(Default)]
-pub struct Ui {
+pub struct Ui<B: Backend> {
     layouts: Vec<Layout>,
     pub screen: VirtualScreen,
+    backend: B,
+    // the next frame is drawn in full instead of as patches, e.g. after a resize
+    repaint: bool,
     //key: Option<i32>,
 }
 
-impl Ui {
-    pub fn new(width: usize, height: usize) -> Self {
-        let ret = Self {
-            screen: VirtualScreen::new(width, height),
+impl<B: Backend> Ui<B> {
+    // Takes over `backend` with `screen`, set up with its palette and blank colours
+    pub fn new(screen: VirtualScreen, mut backend: B) -> Self {
+        screen.repaint(&mut backend).unwrap();
+        Self {
+            screen,
+            backend,
+            repaint: false,
             layouts: Vec::default(),
-        };
-        ret.screen.flush(&mut stdout()).unwrap();
-        ret
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        self.screen.size()
+    }
+
+    #[cfg(test)]
+    pub fn backend(&self) -> &B {
+        &self.backend
     }
 
+    // The terminal may have reflowed or cleared what was on it, so the next
+    // frame is drawn from scratch
     pub fn resize(&mut self, width: usize, height: usize) {
         self.screen.resize(width, height);
-        self.screen.flush(&mut stdout()).unwrap();
+        self.repaint = true;
     }
 
     pub fn begin(&mut self, pos: Vec2, kind: LayoutKind) {
@@ -136,38 +159,94 @@ impl Ui {
             .add_widget(layout.size);
     }
 
-    pub fn label_fixed_width(&mut self, text: &str, width: i32, fg: Color, bg: Color) -> Vec2 {
-        // TODO(#17): Ui::label_fixed_width() does not elide the text when width < text.len()
+    // Empty room of `width` columns that keeps the widgets around it apart
+    pub fn space(&mut self, width: i32) {
+        self.layouts
+            .last_mut()
+            .expect("Trying to add space outside of any layout")
+            .add_widget(Vec2::new(width, 0));
+    }
+
+    // Label padded to `width` columns, longer text is cut off. The padding
+    // has no underline or strikethrough, they would run on past the text.
+    pub fn label_fixed_width(&mut self, text: &str, width: i32, style: Style) -> Vec2 {
         let layout = self
             .layouts
             .last_mut()
             .expect("Trying to render label outside of any layout");
 
         let pos = layout.available_pos();
-
-        self.screen.put_cells(pos.x as usize, pos.y as usize, text, fg, bg);
-        let fill = std::iter::repeat(" ").take((layout.size.x-width-4).abs() as usize).collect::<String>();
-        self.screen.put_cells((pos.x + width) as usize, pos.y as usize, &fill, fg, bg);
-        
         layout.add_widget(Vec2::new(width, 1));
 
+        let width = width.max(0) as usize;
+        let shown = fit(text, width);
+        let len = text_width(shown);
+        let Style { fg, bg, attrs } = style;
+        self.screen.put_cells(pos.x as usize, pos.y as usize, shown, fg, bg, attrs);
+        let fill = " ".repeat(width - len);
+        let fill_attrs = attrs - (Attrs::UNDERLINE | Attrs::STRIKETHROUGH);
+        self.screen.put_cells(pos.x as usize + len, pos.y as usize, &fill, fg, bg, fill_attrs);
+
         pos
     }
 
-    pub fn label_edit(&mut self, text: &str, fg: Color, bg: Color, edit: bool) {
-        let len = text.chars().count();
-        let pos = self.label_fixed_width(text, len as i32, fg, bg);
-        if edit {
-            self.screen.put_cell(pos.x as usize + len, pos.y as usize, ' ', fg, fg);
-            self.screen.put_cell(pos.x as usize + len + 1, pos.y as usize, ' ', bg, bg);
-        } else {
-            self.screen.put_cell(pos.x as usize + len, pos.y as usize, ' ', bg, bg);
+    // Label with a block caret in `caret_style` on the grapheme at column
+    // `caret`, or after the text. Text that doesn't fit is scrolled to keep
+    // the caret in view.
+    pub fn label_edit(
+        &mut self,
+        text: &str,
+        width: i32,
+        style: Style,
+        caret: usize,
+        caret_style: Style,
+    ) {
+        let mut under = " ";
+        let mut column = 0;
+        for grapheme in text.graphemes(true) {
+            if column >= caret {
+                under = grapheme;
+                break;
+            }
+            column += grapheme_width(grapheme);
+        }
+        let caret_end = caret + grapheme_width(under).max(1);
+        // Whole graphemes scroll off the start until the caret fits
+        let room = width.max(0) as usize;
+        let mut shown = text;
+        let mut skip = 0;
+        while caret_end > skip + room {
+            let Some(grapheme) = shown.graphemes(true).next() else {
+                break;
+            };
+            skip += grapheme_width(grapheme);
+            shown = &shown[grapheme.len()..];
+        }
+        let pos = self.label_fixed_width(shown, width, style);
+        if caret_end > skip + room {
+            return;
+        }
+        let Style { fg, bg, attrs } = caret_style;
+        let x = pos.x as usize + caret - skip;
+        self.screen.put_cells(x, pos.y as usize, under, fg, bg, attrs);
+    }
+
+    // Label with the byte ranges of `text` in their own styles, later spans
+    // are drawn over earlier ones
+    pub fn label_highlight(&mut self, text: &str, width: i32, style: Style, spans: &[Span]) {
+        let pos = self.label_fixed_width(text, width, style);
+        let width = width.max(0) as usize;
+        for (range, Style { fg, bg, attrs }) in spans {
+            let column = text_width(&text[..range.start]);
+            let shown = fit(&text[range.clone()], width.saturating_sub(column));
+            let x = pos.x as usize + column;
+            self.screen.put_cells(x, pos.y as usize, shown, *fg, *bg, *attrs);
         }
     }
 
     #[allow(dead_code)]
-    pub fn label(&mut self, text: &str, fg: Color, bg: Color) {
-        self.label_fixed_width(text, text.len() as i32, fg, bg);
+    pub fn label(&mut self, text: &str, style: Style) {
+        self.label_fixed_width(text, text_width(text) as i32, style);
     }
 
     pub fn end(&mut self) {
@@ -175,10 +254,13 @@ impl Ui {
             .pop()
             .expect("Unbalanced Ui::begin() and Ui::end() calls.");
 
-        let mut stdout = stdout();
-        apply_patches(&mut stdout, &self.screen.diff()).unwrap();
-
-        self.screen.swap();
-        stdout.flush().unwrap();
+        if self.repaint {
+            self.repaint = false;
+            self.screen.swap();
+            self.screen.repaint(&mut self.backend).unwrap();
+        } else {
+            self.backend.patch(&self.screen.diff()).unwrap();
+            self.screen.swap();
+        }
     }
 }
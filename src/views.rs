@@ -0,0 +1,72 @@
+// Saved queries ("views") the filter can be switched to with `v` and `V`
+// or used with `list --view <name>`. They are read from
+// `$XDG_CONFIG_HOME/todo-rs/views.toml` (or `~/.config/todo-rs/views.toml`):
+//
+//     [[view]]
+//     name = "Backend"
+//     query = "status:todo tag:backend"
+//
+//     [[view]]
+//     name = "Overdue"
+//     query = "is:overdue"
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::config;
+use crate::query::Query;
+
+#[derive(Debug, Clone)]
+pub struct View {
+    pub name: String,
+    pub query: Query,
+}
+
+#[derive(Deserialize)]
+struct ViewsFile {
+    #[serde(default)]
+    view: Vec<ViewEntry>,
+}
+
+#[derive(Deserialize)]
+struct ViewEntry {
+    name: String,
+    query: String,
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join("views.toml"))
+}
+
+// The views in file order, none if the file doesn't exist
+pub fn load(path: &Path) -> Result<Vec<View>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("could not read {}", path.display())),
+    };
+    let file: ViewsFile =
+        toml::from_str(&text).with_context(|| format!("could not parse {}", path.display()))?;
+    file.view
+        .into_iter()
+        .map(|entry| {
+            let query = Query::parse(&entry.query).map_err(|message| {
+                anyhow!("{}: view `{}`: {}", path.display(), entry.name, message)
+            })?;
+            Ok(View {
+                name: entry.name,
+                query,
+            })
+        })
+        .collect()
+}
+
+pub fn find<'a>(views: &'a [View], name: &str) -> Option<&'a View> {
+    views
+        .iter()
+        .find(|view| view.name.eq_ignore_ascii_case(name))
+}
@@ -0,0 +1,248 @@
+// Vim-style keys for the lists, enabled with --vim
+//
+//     j k       down, up               gg G    first, last (or item <count>)
+//     h l       TODO, DONE list        x       toggle done
+//     dd        delete                 yy      copy
+//     p P       paste below, above     o O     new item below, above
+//     I         edit from the start    A       edit at the end
+//     .         repeat the last change
+//
+// Commands take a count like 5j or 3dd. Edit mode keeps its readline keys,
+// Esc goes back. Keys not listed here work like in the default keymap.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::item::Item;
+use crate::{App, Status};
+
+#[derive(Debug, Default)]
+pub struct Vim {
+    count: Option<usize>,
+    // first key of a two-key command: g, d or y
+    pending: Option<char>,
+    // the items deleted or copied last, for p and P
+    register: Vec<Item>,
+    // with its count, for .
+    last_change: Option<(usize, Change)>,
+    // edit mode was entered by o or O, the typed title belongs to last_change
+    opening: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Change {
+    Delete,
+    Toggle,
+    Paste { below: bool },
+    Open { below: bool, title: String },
+}
+
+// Runs the Vim command for `event`, false if it is left to the default keymap
+pub fn handle_key(app: &mut App, event: KeyEvent) -> bool {
+    let Some(mut vim) = app.vim.take() else {
+        return false;
+    };
+    let handled = vim.key(app, event);
+    app.vim = Some(vim);
+    handled
+}
+
+impl Vim {
+    // The count and keys typed so far, for the status bar
+    pub fn pending(&self) -> String {
+        let mut pending = self.count.map(|count| count.to_string()).unwrap_or_default();
+        pending.extend(self.pending);
+        pending
+    }
+
+    // Edit mode ended with `title`
+    pub fn insert_finished(&mut self, title: &str) {
+        if let Some((_, Change::Open { title: typed, .. })) = &mut self.last_change {
+            if self.opening {
+                *typed = title.to_string();
+            }
+        }
+        self.opening = false;
+    }
+
+    fn key(&mut self, app: &mut App, event: KeyEvent) -> bool {
+        let typed = self.count.is_some() || self.pending.is_some();
+        let KeyCode::Char(c) = event.code else {
+            self.count = None;
+            self.pending = None;
+            // Esc cancels a half typed command instead of quitting
+            return typed && event.code == KeyCode::Esc;
+        };
+        if event
+            .modifiers
+            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+        {
+            self.count = None;
+            self.pending = None;
+            return false;
+        }
+
+        if let Some(first) = self.pending.take() {
+            let count = self.count.take();
+            match (first, c) {
+                ('g', 'g') => go_to(app, count.unwrap_or(1)),
+                ('d', 'd') => self.change(app, count.unwrap_or(1), Change::Delete),
+                ('y', 'y') => self.copy(app, count.unwrap_or(1)),
+                _ => {}
+            }
+            return true;
+        }
+        match c {
+            // a leading 0 is no count
+            '0'..='9' if c != '0' || self.count.is_some() => {
+                let digit = c.to_digit(10).unwrap_or_default() as usize;
+                let count = self.count.unwrap_or(0).saturating_mul(10);
+                self.count = Some(count.saturating_add(digit));
+                return true;
+            }
+            'g' | 'd' | 'y' => {
+                self.pending = Some(c);
+                return true;
+            }
+            _ => {}
+        }
+
+        let count = self.count.take();
+        let times = count.unwrap_or(1);
+        match c {
+            'j' => app.cursor_down_by(times),
+            'k' => app.cursor_up_by(times),
+            'G' => match count {
+                Some(count) => go_to(app, count),
+                None => app.cursor_to_bottom(),
+            },
+            'h' => app.active_status = Status::Todo,
+            'l' => app.active_status = Status::Done,
+            'x' => self.change(app, times, Change::Toggle),
+            'p' => self.change(app, times, Change::Paste { below: true }),
+            'P' => self.change(app, times, Change::Paste { below: false }),
+            'o' | 'O' => {
+                let below = c == 'o';
+                if open(app, below, "") {
+                    app.set_edit(true);
+                    self.last_change = Some((1, Change::Open { below, title: String::new() }));
+                    self.opening = true;
+                }
+            }
+            'I' | 'A' => {
+                if app.active_status == Status::Todo && !app.active_items().is_empty() {
+                    app.set_edit(true);
+                    if c == 'I' {
+                        app.editor.home();
+                    }
+                }
+            }
+            '.' => match self.last_change.clone() {
+                Some((last_count, change)) => self.run(app, count.unwrap_or(last_count), &change),
+                None => app.message = Some("Nothing to repeat".to_string()),
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    fn change(&mut self, app: &mut App, times: usize, change: Change) {
+        self.run(app, times, &change);
+        self.last_change = Some((times, change));
+    }
+
+    // Runs `change` `times` times as one undoable step
+    fn run(&mut self, app: &mut App, times: usize, change: &Change) {
+        app.batch(|app| self.run_steps(app, times, change));
+    }
+
+    fn run_steps(&mut self, app: &mut App, times: usize, change: &Change) {
+        match change {
+            Change::Delete => {
+                let mut deleted = Vec::new();
+                for _ in 0..times {
+                    if app.active_items().is_empty() {
+                        break;
+                    }
+                    deleted.push(app.active_items()[app.active_cursor()].clone());
+                    app.list_delete();
+                }
+                if !deleted.is_empty() {
+                    self.register = deleted;
+                }
+            }
+            Change::Toggle => {
+                for _ in 0..times {
+                    app.list_transfer();
+                }
+            }
+            Change::Paste { below } => {
+                if self.register.is_empty() {
+                    app.message = Some("Nothing to paste, dd or yy first".to_string());
+                    return;
+                }
+                paste(app, *below, &self.register, times);
+            }
+            Change::Open { below, title } => {
+                for _ in 0..times {
+                    open(app, *below, title);
+                }
+            }
+        }
+    }
+
+    fn copy(&mut self, app: &mut App, times: usize) {
+        let rows = app.rows(app.active_status);
+        let row = app.active_list().row(&rows);
+        self.register = rows
+            .iter()
+            .skip(row)
+            .take(times)
+            .map(|&index| app.active_items()[index].clone())
+            .collect();
+    }
+}
+
+// Moves the cursor to the `number`th shown item, counted from 1
+fn go_to(app: &mut App, number: usize) {
+    let rows = app.rows(app.active_status);
+    if let Some(&index) = rows.get(number.saturating_sub(1)).or(rows.last()) {
+        app.active_list_mut().cursor = index;
+    }
+}
+
+// Where o, O, p and P put items
+fn insert_position(app: &App, below: bool) -> usize {
+    if below && !app.active_items().is_empty() {
+        app.active_cursor() + 1
+    } else {
+        app.active_cursor()
+    }
+}
+
+// Inserts an item titled `title` next to the cursor, only TODO items are added this way
+fn open(app: &mut App, below: bool, title: &str) -> bool {
+    if app.active_status != Status::Todo {
+        app.message = Some("New items go to TODO, h to switch".to_string());
+        return false;
+    }
+    app.active_list_mut().cursor = insert_position(app, below);
+    app.insert_item(Item::new(title));
+    true
+}
+
+// Inserts `times` copies of `items` one after the other next to the cursor,
+// which ends up on the first one
+fn paste(app: &mut App, below: bool, items: &[Item], times: usize) {
+    let position = insert_position(app, below);
+    for (offset, item) in items.iter().cycle().take(items.len() * times).enumerate() {
+        let mut item = item.clone();
+        match app.active_status {
+            Status::Todo => item.uncomplete(),
+            Status::Done if item.completed.is_none() => item.complete(),
+            Status::Done => {}
+        }
+        app.active_list_mut().cursor = position + offset;
+        app.insert_item(item);
+    }
+    app.active_list_mut().cursor = position;
+}